@@ -0,0 +1,416 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+use std::ffi::{CString, OsString};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::ptr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How `write` got the data into the file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Written {
+    /// Through a synced temporary file renamed over the target.
+    Replaced,
+    /// Directly over the target, which a crash can leave half written.
+    InPlace(Fallback),
+}
+
+/// Why a file was overwritten in place rather than replaced.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fallback {
+    /// The folder does not allow creating the temporary file.
+    ReadOnlyFolder,
+    /// The file belongs to someone else, and replacing it would have made it
+    /// ours.
+    ForeignOwner,
+}
+
+impl Fallback {
+    /// Explains the fallback to the user.
+    pub fn describe(&self, path: &Path) -> String {
+        let reason = match *self {
+            Fallback::ReadOnlyFolder => "its folder does not allow creating files",
+            Fallback::ForeignOwner => "it belongs to another user",
+        };
+        format!(
+            "{} was overwritten directly because {}, so a crash while saving could have damaged it.",
+            path.display(),
+            reason
+        )
+    }
+}
+
+/// Replaces the contents of `path` with `data` without ever leaving a
+/// half-written file behind: the data goes to a temporary file next to the
+/// target, is synced, and is then renamed over it. Symlinks are followed so
+/// the link itself survives, and the original mode, owner and extended
+/// attributes are given to the temporary file before any data goes in.
+///
+/// Only when the folder does not allow creating the temporary file, or the
+/// owner cannot be kept, is the file overwritten in place, and the returned
+/// `Written` says so.
+pub fn write(path: &Path, data: &[u8]) -> io::Result<Written> {
+    write_with_mode(path, data, 0o666)
+}
+
+/// Like `write`, but a file that does not exist yet is created readable by
+/// its owner only, for what other users have no business reading.
+pub fn write_private(path: &Path, data: &[u8]) -> io::Result<Written> {
+    write_with_mode(path, data, 0o600)
+}
+
+/// `write`, creating a new file with permissions `new_mode` before the
+/// umask.
+fn write_with_mode(path: &Path, data: &[u8], new_mode: u32) -> io::Result<Written> {
+    let target = resolve_symlink(path)?;
+    let original = match fs::metadata(&target) {
+        Ok(metadata) => Some(metadata),
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => None,
+        Err(error) => return Err(error),
+    };
+
+    // Until the original's mode is applied, only we may read what is
+    // written. A new file gets its mode right away.
+    let mode = if original.is_some() { 0o600 } else { new_mode };
+    let (temp_path, mut temp_file) = match create_temp(&target, mode) {
+        Ok(temp) => temp,
+        Err(ref error) if error.kind() == io::ErrorKind::PermissionDenied && !dir_writable(&target) => {
+            return write_in_place(&target, data)
+                .map(|()| Written::InPlace(Fallback::ReadOnlyFolder))
+                .map_err(|error| {
+                    io::Error::new(
+                        error.kind(),
+                        format!(
+                            "the folder of {} is not writable, and overwriting the file directly failed: {}",
+                            target.display(),
+                            error
+                        ),
+                    )
+                });
+        }
+        Err(error) => return Err(error),
+    };
+
+    let result = (|| {
+        if let Some(ref original) = original {
+            if !copy_metadata(original, &target, &temp_file)? {
+                return Ok(false);
+            }
+        }
+        temp_file.write_all(data)?;
+        temp_file.sync_all()?;
+        fs::rename(&temp_path, &target)?;
+        Ok(true)
+    })();
+
+    match result {
+        Ok(true) => {
+            sync_dir(&target);
+            Ok(Written::Replaced)
+        }
+        Ok(false) => {
+            let _ = fs::remove_file(&temp_path);
+            write_in_place(&target, data).map(|()| Written::InPlace(Fallback::ForeignOwner))
+        }
+        Err(error) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(error)
+        }
+    }
+}
+
+/// The file a save should end up in: the final target of `path` if it is a
+/// symlink (even a dangling one), `path` itself otherwise.
+fn resolve_symlink(path: &Path) -> io::Result<PathBuf> {
+    let mut target = path.to_path_buf();
+    for _ in 0..40 {
+        match fs::symlink_metadata(&target) {
+            Ok(ref metadata) if metadata.file_type().is_symlink() => {
+                let link = fs::read_link(&target)?;
+                target = match target.parent() {
+                    Some(parent) => parent.join(link),
+                    None => link,
+                };
+            }
+            Ok(_) => return Ok(target),
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(target),
+            Err(error) => return Err(error),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        format!("too many levels of symbolic links at {}", path.display()),
+    ))
+}
+
+/// The folder `target` is in.
+fn dir_of(target: &Path) -> &Path {
+    match target.parent() {
+        Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+        Some(dir) => dir,
+        None => Path::new("."),
+    }
+}
+
+/// Whether new files can be created in the folder of `target`.
+fn dir_writable(target: &Path) -> bool {
+    match CString::new(dir_of(target).as_os_str().as_bytes()) {
+        Ok(dir) => unsafe { libc::access(dir.as_ptr(), libc::W_OK) == 0 },
+        Err(_) => false,
+    }
+}
+
+/// Creates a new hidden file next to `target` with permissions `mode`.
+fn create_temp(target: &Path, mode: u32) -> io::Result<(PathBuf, File)> {
+    let dir = dir_of(target);
+    let name = target.file_name().unwrap_or_default();
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.subsec_nanos())
+        .unwrap_or(0);
+
+    let mut last_error = None;
+    for attempt in 0..16u32 {
+        let mut temp_name = OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(".eddit-{:x}-{:x}", process::id(), seed.wrapping_add(attempt)));
+        let temp_path = dir.join(temp_name);
+
+        match OpenOptions::new().write(true).create_new(true).mode(mode).open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(ref error) if error.kind() == io::ErrorKind::AlreadyExists => (),
+            Err(error) => {
+                last_error = Some(error);
+                break;
+            }
+        }
+    }
+
+    let error = last_error.unwrap_or_else(|| io::Error::from(io::ErrorKind::AlreadyExists));
+    Err(io::Error::new(
+        error.kind(),
+        format!("cannot create a temporary file in {}: {}", dir.display(), error),
+    ))
+}
+
+/// Gives the temporary file the original's mode, owner and extended
+/// attributes. Returns false if the owner could not be kept, in which case
+/// replacing the file would silently hand it over to the current user.
+fn copy_metadata(original: &fs::Metadata, target: &Path, temp_file: &File) -> io::Result<bool> {
+    if unsafe { libc::fchown(temp_file.as_raw_fd(), original.uid(), original.gid()) } != 0 {
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() != Some(libc::EPERM) {
+            return Err(error);
+        }
+        // Without CAP_CHOWN we can still keep a group we belong to, which is
+        // enough when the file was already ours.
+        if original.uid() != unsafe { libc::geteuid() } {
+            return Ok(false);
+        }
+    }
+
+    copy_xattrs(target, temp_file);
+    temp_file.set_permissions(original.permissions())?;
+    Ok(true)
+}
+
+/// Copies the extended attributes of `target` onto `temp_file`. Attributes
+/// such as security.* may be off limits, and the file content matters more
+/// than them, so failures are skipped.
+fn copy_xattrs(target: &Path, temp_file: &File) {
+    let target = match CString::new(target.as_os_str().as_bytes()) {
+        Ok(target) => target,
+        Err(_) => return,
+    };
+
+    let names = match read_xattr(|buf, size| unsafe { libc::listxattr(target.as_ptr(), buf, size) }) {
+        Some(names) => names,
+        None => return,
+    };
+
+    for name in names.split(|&b| b == 0).filter(|name| !name.is_empty()) {
+        let name = match CString::new(name) {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        let value = read_xattr(|buf, size| unsafe {
+            libc::getxattr(target.as_ptr(), name.as_ptr(), buf as *mut libc::c_void, size)
+        });
+        if let Some(value) = value {
+            unsafe {
+                libc::fsetxattr(
+                    temp_file.as_raw_fd(),
+                    name.as_ptr(),
+                    value.as_ptr() as *const libc::c_void,
+                    value.len(),
+                    0,
+                );
+            }
+        }
+    }
+}
+
+/// Runs one of the xattr calls that report the needed size when given an
+/// empty buffer, retrying if the attribute grows in between.
+fn read_xattr<F: Fn(*mut libc::c_char, libc::size_t) -> libc::ssize_t>(call: F) -> Option<Vec<u8>> {
+    for _ in 0..4 {
+        let size = call(ptr::null_mut(), 0);
+        if size < 0 {
+            return None;
+        }
+        let mut buf = vec![0u8; size as usize];
+        let read = call(buf.as_mut_ptr() as *mut libc::c_char, buf.len());
+        if read >= 0 {
+            buf.truncate(read as usize);
+            return Some(buf);
+        }
+        if io::Error::last_os_error().raw_os_error() != Some(libc::ERANGE) {
+            return None;
+        }
+    }
+    None
+}
+
+fn write_in_place(target: &Path, data: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(target)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+/// Makes the rename itself durable. Failing here does not lose data, so
+/// errors are ignored.
+fn sync_dir(target: &Path) {
+    if let Ok(dir) = File::open(dir_of(target)) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    /// An empty folder for one test, removed again when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let dir = std::env::temp_dir().join(format!("eddit-atomic-{}-{}", name, process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::set_permissions(&self.0, fs::Permissions::from_mode(0o755));
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o7777
+    }
+
+    #[test]
+    fn replaces_the_file() {
+        let dir = TempDir::new("replace");
+        let path = dir.0.join("file.txt");
+        fs::write(&path, "old").unwrap();
+
+        assert_eq!(write(&path, b"new").unwrap(), Written::Replaced);
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn follows_symlinks() {
+        let dir = TempDir::new("symlink");
+        let real = dir.0.join("real.txt");
+        let link = dir.0.join("link.txt");
+        fs::write(&real, "old").unwrap();
+        symlink("real.txt", &link).unwrap();
+
+        assert_eq!(write(&link, b"new").unwrap(), Written::Replaced);
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new("real.txt"));
+        assert_eq!(fs::read(&real).unwrap(), b"new");
+    }
+
+    #[test]
+    fn creates_the_target_of_a_dangling_symlink() {
+        let dir = TempDir::new("dangling");
+        let link = dir.0.join("link.txt");
+        symlink("missing.txt", &link).unwrap();
+
+        assert_eq!(write(&link, b"new").unwrap(), Written::Replaced);
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read(dir.0.join("missing.txt")).unwrap(), b"new");
+    }
+
+    #[test]
+    fn creates_private_files() {
+        let dir = TempDir::new("private");
+        let path = dir.0.join("file.txt");
+
+        write_private(&path, b"new").unwrap();
+        assert_eq!(mode(&path), 0o600);
+    }
+
+    #[test]
+    fn keeps_the_mode() {
+        let dir = TempDir::new("mode");
+        for &wanted in [0o600, 0o640, 0o755].iter() {
+            let path = dir.0.join(format!("{:o}.txt", wanted));
+            fs::write(&path, "old").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(wanted)).unwrap();
+
+            write(&path, b"new").unwrap();
+            assert_eq!(mode(&path), wanted);
+            assert_eq!(fs::read(&path).unwrap(), b"new");
+        }
+    }
+
+    #[test]
+    fn overwrites_in_place_in_a_read_only_folder() {
+        // Root may create files anywhere, so there is no fallback to see.
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+        let dir = TempDir::new("read-only");
+        let path = dir.0.join("file.txt");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&dir.0, fs::Permissions::from_mode(0o555)).unwrap();
+
+        assert_eq!(write(&path, b"new").unwrap(), Written::InPlace(Fallback::ReadOnlyFolder));
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert!(write(&dir.0.join("other.txt"), b"new").is_err());
+    }
+}
@@ -0,0 +1,282 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+use crate::encoding::TextEncoding;
+use std::path::{Path, PathBuf};
+
+pub const USAGE: &str = "\
+Usage: com.github.maze-n.eddit [OPTION…] [FILE[:LINE[:COLUMN]]…]
+
+Opens each FILE in a tab. A FILE of - reads the text from standard input.
+
+Options:
+  +LINE[:COLUMN]       put the cursor there in the next FILE; + alone goes
+                       to the last line
+  --new-window         open the files in a new window
+  --wait               return only once the files are closed, as $EDITOR
+  --readonly           open the files without allowing changes
+  --encoding=NAME      read the files as NAME, such as UTF-8 or
+                       windows-1252, instead of guessing
+  -h, --help           show this help and exit
+  -v, --version        show the version and exit
+";
+
+/// What the command line asks for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Run(Options),
+    Help,
+    Version,
+}
+
+/// A file to open, and where to put the cursor in it. Lines and columns
+/// count from 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileArg {
+    pub path: PathBuf,
+    pub line: Option<i32>,
+    pub column: Option<i32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Options {
+    pub files: Vec<FileArg>,
+    pub stdin: bool,
+    pub new_window: bool,
+    pub wait: bool,
+    pub readonly: bool,
+    pub encoding: Option<TextEncoding>,
+}
+
+/// Parses the arguments after the program name. Relative paths are taken
+/// from `cwd`, the directory the command was run in.
+pub fn parse<I: IntoIterator<Item = String>>(args: I, cwd: &Path) -> Result<Command, String> {
+    let mut options = Options::default();
+    // The `+LINE` argument waiting for its file, as typed and as read.
+    let mut position: Option<(String, (i32, Option<i32>))> = None;
+    let mut only_files = false;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        if only_files {
+            options.files.push(file_arg(&arg, position.take().map(|(_, position)| position), cwd));
+            continue;
+        }
+
+        match arg.as_str() {
+            "--" => only_files = true,
+            "-" => options.stdin = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            "-v" | "--version" => return Ok(Command::Version),
+            "--new-window" => options.new_window = true,
+            "--wait" => options.wait = true,
+            "--readonly" => options.readonly = true,
+            "--encoding" => {
+                let name = args.next().ok_or_else(|| "--encoding needs an encoding name".to_string())?;
+                options.encoding = Some(encoding(&name)?);
+            }
+            _ if arg.starts_with("--encoding=") => {
+                options.encoding = Some(encoding(&arg["--encoding=".len()..])?);
+            }
+            _ if arg.starts_with('+') => {
+                let parsed = parse_position(&arg[1..]).ok_or_else(|| format!("invalid position “{}”", arg))?;
+                position = Some((arg, parsed));
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option “{}”", arg)),
+            _ => options.files.push(file_arg(&arg, position.take().map(|(_, position)| position), cwd)),
+        }
+    }
+
+    if let Some((arg, _)) = position {
+        return Err(format!("{} is not followed by a file", arg));
+    }
+    Ok(Command::Run(options))
+}
+
+fn encoding(name: &str) -> Result<TextEncoding, String> {
+    TextEncoding::from_label(name).ok_or_else(|| format!("unsupported encoding “{}”", name))
+}
+
+/// Reads `LINE` or `LINE:COLUMN`. An empty position means the last line.
+fn parse_position(position: &str) -> Option<(i32, Option<i32>)> {
+    if position.is_empty() {
+        return Some((i32::MAX, None));
+    }
+    match position.find(':') {
+        Some(colon) => Some((number(&position[..colon])?, Some(number(&position[colon + 1..])?))),
+        None => Some((number(position)?, None)),
+    }
+}
+
+fn number(text: &str) -> Option<i32> {
+    text.parse().ok().filter(|&number| number > 0)
+}
+
+/// A `+LINE` before the file wins; otherwise a `:LINE` or `:LINE:COLUMN`
+/// suffix, as compilers print, is split off unless the whole argument names
+/// an existing file.
+fn file_arg(arg: &str, position: Option<(i32, Option<i32>)>, cwd: &Path) -> FileArg {
+    let position = position.map(|(line, column)| (arg, line, column)).or_else(|| {
+        if cwd.join(arg).exists() {
+            None
+        } else {
+            split_position(arg)
+        }
+    });
+
+    match position {
+        Some((path, line, column)) => FileArg {
+            path: cwd.join(path),
+            line: Some(line),
+            column,
+        },
+        None => FileArg {
+            path: cwd.join(arg),
+            line: None,
+            column: None,
+        },
+    }
+}
+
+fn split_position(arg: &str) -> Option<(&str, i32, Option<i32>)> {
+    let colon = arg.rfind(':')?;
+    let (rest, last) = (&arg[..colon], number(&arg[colon + 1..])?);
+    let split = match rest.rfind(':') {
+        Some(colon) => match number(&rest[colon + 1..]) {
+            Some(line) => (&rest[..colon], line, Some(last)),
+            None => (rest, last, None),
+        },
+        None => (rest, last, None),
+    };
+    if split.0.is_empty() {
+        None
+    } else {
+        Some(split)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn run(args: &[&str]) -> Result<Options, String> {
+        let args = args.iter().map(|arg| arg.to_string());
+        match parse(args, Path::new("/work"))? {
+            Command::Run(options) => Ok(options),
+            command => panic!("expected options, got {:?}", command),
+        }
+    }
+
+    fn file(path: &str, line: Option<i32>, column: Option<i32>) -> FileArg {
+        FileArg {
+            path: PathBuf::from(path),
+            line,
+            column,
+        }
+    }
+
+    #[test]
+    fn position_suffix() {
+        let options = run(&["src/main.rs:12:5", "notes.txt:3", "plain.txt"]).unwrap();
+        assert_eq!(
+            options.files,
+            vec![
+                file("/work/src/main.rs", Some(12), Some(5)),
+                file("/work/notes.txt", Some(3), None),
+                file("/work/plain.txt", None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn position_before_file() {
+        let options = run(&["+7", "a.txt", "+2:4", "b.txt:9", "+", "c.txt"]).unwrap();
+        assert_eq!(
+            options.files,
+            vec![
+                file("/work/a.txt", Some(7), None),
+                file("/work/b.txt:9", Some(2), Some(4)),
+                file("/work/c.txt", Some(i32::MAX), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn position_without_file() {
+        assert_eq!(run(&["a.txt", "+5"]), Err("+5 is not followed by a file".to_string()));
+        assert!(run(&["+x", "a.txt"]).is_err());
+    }
+
+    #[test]
+    fn stdin_and_end_of_options() {
+        let options = run(&["-", "--", "--wait", "-"]).unwrap();
+        assert!(options.stdin);
+        assert!(!options.wait);
+        assert_eq!(
+            options.files,
+            vec![file("/work/--wait", None, None), file("/work/-", None, None)]
+        );
+    }
+
+    #[test]
+    fn encoding_option() {
+        let separate = run(&["--encoding", "windows-1252"]).unwrap();
+        let joined = run(&["--encoding=windows-1252"]).unwrap();
+        assert!(separate.encoding.is_some());
+        assert_eq!(separate.encoding, joined.encoding);
+        assert!(run(&["--encoding"]).is_err());
+        assert!(run(&["--encoding=no-such-encoding"]).is_err());
+    }
+
+    #[test]
+    fn unknown_option() {
+        assert_eq!(run(&["--frobnicate"]), Err("unknown option “--frobnicate”".to_string()));
+        assert_eq!(parse(vec!["-h".to_string()], Path::new("/")), Ok(Command::Help));
+    }
+
+    #[test]
+    fn existing_file_with_colon() {
+        let dir = std::env::temp_dir().join(format!("eddit-cli-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("notes:12"), "").unwrap();
+
+        let result = parse(vec!["notes:12".to_string(), "other:12".to_string()], &dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        match result {
+            Ok(Command::Run(options)) => assert_eq!(
+                options.files,
+                vec![
+                    FileArg {
+                        path: dir.join("notes:12"),
+                        line: None,
+                        column: None,
+                    },
+                    FileArg {
+                        path: dir.join("other"),
+                        line: Some(12),
+                        column: None,
+                    },
+                ]
+            ),
+            other => panic!("expected options, got {:?}", other),
+        }
+    }
+}
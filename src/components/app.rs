@@ -19,37 +19,39 @@
 
 use super::file_operations::*;
 use super::misc::*;
-use super::{Content, Header, SearchBox, UnsavedDialog};
-use crate::state::ActiveMetadata;
-use gio::SettingsExt;
+use super::{Document, ErrorDialog, FindInFiles, Header, RecentFiles, SearchBox, StatusBar, Tabs};
+use crate::encoding::TextEncoding;
+use crate::goto::{self, Target};
+use crate::line_ending::LineEnding;
+use crate::search::{Query, Replacement};
+use gio::{ActionGroupExt, SettingsExt};
 use gtk::SettingsExt as GTKSettingsExt;
 use gtk::*;
 use pango::*;
 use sourceview::*;
+use std::cell::RefCell;
 use std::env;
-use std::sync::{Arc, RwLock};
+
+/// Seconds between snapshots of unsaved documents into the recovery journal.
+const RECOVERY_INTERVAL: u32 = 10;
 
 pub struct App {
     pub window: Window,
     pub header: Header,
-    pub content: Content,
+    pub tabs: Tabs,
     pub search_bar: SearchBox,
     pub revealer: Revealer,
-    pub path_label: Label,
+    pub find_in_files: FindInFiles,
+    pub status_bar: StatusBar,
 }
 
 pub struct ConnectedApp(App);
 
 impl App {
-    pub fn new() -> App {
-        if gtk::init().is_err() {
-            eprintln!("Failed to initialize GTK");
-            std::process::exit(1);
-        }
-
+    pub fn new(application: &Application) -> App {
         let window = Window::new(WindowType::Toplevel);
+        application.add_window(&window);
         let header = Header::new();
-        let content = Content::new();
 
         let window_box = Box::new(Orientation::Vertical, 0);
 
@@ -58,15 +60,8 @@ impl App {
         revealer.set_transition_type(RevealerTransitionType::SlideDown);
         revealer.add(&search_bar.container);
 
-        let bottom_box = Box::new(Orientation::Horizontal, 0);
-        bottom_box.set_border_width(4);
-
-        let path_label = Label::new(Some("Unsaved file"));
-        path_label.set_selectable(true);
-        path_label.set_ellipsize(EllipsizeMode::Start);
-        path_label.set_halign(Align::Start);
-
-        bottom_box.pack_start(&path_label, false, false, 0);
+        let status_bar = StatusBar::new();
+        let tabs = Tabs::new(&window, &header, &status_bar, &search_bar);
 
         let settings = gio::Settings::new("com.github.maze-n.eddit");
         let pos_x = settings.get_int("pos-x");
@@ -91,8 +86,13 @@ impl App {
         }
 
         window_box.pack_start(&revealer, false, true, 0);
-        window_box.pack_start(&content.container, true, true, 0);
-        window_box.pack_start(&bottom_box, false, false, 0);
+        let find_in_files = FindInFiles::new();
+        let paned = Paned::new(Orientation::Horizontal);
+        paned.pack1(&find_in_files.container, false, false);
+        paned.pack2(&tabs.notebook, true, false);
+
+        window_box.pack_start(&paned, true, true, 0);
+        window_box.pack_start(&status_bar.container, false, false, 0);
 
         window.get_style_context().add_class("rounded");
         window.set_titlebar(Some(&header.container));
@@ -100,73 +100,90 @@ impl App {
         window.set_default_size(800, 600);
         window.add(&window_box);
 
+        tabs.add();
+
         App {
             window,
             header,
-            content,
+            tabs,
             search_bar,
             revealer,
-            path_label,
+            find_in_files,
+            status_bar,
         }
     }
 
     pub fn connect_events(self) -> ConnectedApp {
-        let current_file = Arc::new(RwLock::new(None));
         {
-            let save = &self.header.save;
-            let save_as = &self.header.save_as;
-
-            self.window_quit(&self.window, current_file.clone());
+            self.window_quit(&self.window);
             self.theme_changed(&self.header.theme_switch);
-            self.editor_changed(current_file.clone(), &self.header.save.clone());
-            self.open_file(current_file.clone());
-            self.save_file(&save.clone(), &save.clone(), current_file.clone(), false);
-            self.save_file(&save, &save_as, current_file.clone(), true);
+            self.new_document(&self.header.new_tab);
+            self.open_file(&self.header.open);
+            self.recent_files(&self.header.recent);
+            self.file_drops(&self.window);
+            self.save_file(&self.header.save, false);
+            self.save_file(&self.header.save_as, true);
+            self.encoding_changed(&self.status_bar.encoding);
+            self.line_ending_changed(&self.status_bar.line_ending);
+            self.language_changed(&self.status_bar.language);
+            self.indentation_changed(&self.status_bar);
             self.font_changed(&self.header.font_button);
-            self.set_text_wrap_mode(&self.header, &self.content.view);
+            self.set_text_wrap_mode(&self.header);
+            self.autosave_changed(&self.header);
+            self.restore_session_changed(&self.header.restore_session);
+            self.large_file_threshold_changed(&self.header.large_file_threshold);
+            self.recovery_journal();
             self.find_replace(&self.header.find_button, &self.revealer, &self.search_bar.search_entry);
-            self.key_events(current_file);
+            self.find_in_files(&self.header.find_in_files_button);
+            self.go_to_line(&self.header);
+            self.key_events();
         }
         ConnectedApp(self)
     }
 
-    fn window_quit(&self, window: &Window, current_file: Arc<RwLock<Option<ActiveMetadata>>>) {
+    fn window_quit(&self, window: &Window) {
         let window_clone = window.clone();
-        let save_button = self.header.save.clone();
-        let editor = self.content.buff.clone();
-        let window = self.window.clone();
+        let tabs = self.tabs.clone();
         let word_wrap_checkbox = self.header.enable_wrapping_word.clone();
         let char_wrap_checkbox = self.header.enable_wrapping_char.clone();
 
-        window.connect_delete_event(move |window, _| {
+        let settings = gio::Settings::new("com.github.maze-n.eddit");
+
+        window.connect_delete_event(move |_, _| {
             before_quit(&window_clone, &word_wrap_checkbox, &char_wrap_checkbox);
-            if save_button.get_sensitive() {
-                let dialog = UnsavedDialog::new(&window);
-                let result = dialog.run();
-                if result == ResponseType::Yes.into() {
-                    if save_before_close(&editor, &window, &save_button, &current_file) {
-                        main_quit();
-                        Inhibit(false)
-                    } else {
-                        Inhibit(true)
+
+            // Closing the last window keeps its documents, changes and all,
+            // for the next launch instead of asking about them.
+            let is_last = window_clone
+                .get_application()
+                .map_or(true, |application| application.get_windows().len() <= 1);
+            if is_last && settings.get_boolean("restore-session") {
+                match save_session(&tabs) {
+                    Ok(()) => {
+                        tabs.discard_all();
+                        return Inhibit(false);
+                    }
+                    // Without a session the changes would be lost, so ask
+                    // about them as usual instead.
+                    Err(error) => {
+                        let message = format!("{}\n\nUnsaved changes will not be kept for the next launch.", error);
+                        let error_dialog = ErrorDialog::new(&window_clone, "Failed to save the session", &message);
+                        let _ = error_dialog.run();
                     }
-                } else if result == ResponseType::No.into() {
-                    main_quit();
-                    Inhibit(false)
-                } else {
-                    Inhibit(true)
                 }
-            } else {
-                main_quit();
+            }
+
+            if tabs.confirm_close_all() {
                 Inhibit(false)
+            } else {
+                Inhibit(true)
             }
         });
     }
 
     fn theme_changed(&self, theme_switch: &Switch) {
         let settings = gio::Settings::new("com.github.maze-n.eddit");
-        let source_style_manager = self.content.style_manager.clone();
-        let buff = self.content.buff.clone();
+        let tabs = self.tabs.clone();
 
         if let Some(gtk_settings) = Settings::get_default() {
             theme_switch.set_state(gtk_settings.get_property_gtk_application_prefer_dark_theme());
@@ -175,94 +192,233 @@ impl App {
             if let Some(gtk_settings) = Settings::get_default() {
                 gtk_settings.set_property_gtk_application_prefer_dark_theme(!theme_switch.get_state());
                 settings.set_boolean("is-dark", !theme_switch.get_state());
-                if !theme_switch.get_state() {
-                    source_style_manager
-                        .get_scheme ("eddit-dark")
-                        .or (source_style_manager.get_scheme ("Classic"))
-                        .map (|theme| buff.set_style_scheme (Some(&theme)));
-                } else {
-                    source_style_manager
-                        .get_scheme ("eddit-light")
-                        .or (source_style_manager.get_scheme ("Classic"))
-                        .map (|theme| buff.set_style_scheme (Some(&theme)));
+                for document in tabs.documents() {
+                    document.content.set_dark(!theme_switch.get_state());
                 }
             }
             Inhibit(false)
         });
     }
 
-    fn editor_changed(&self, current_file: Arc<RwLock<Option<ActiveMetadata>>>, save_button: &Button) {
-        let save_button = save_button.clone();
-        self.content.buff.connect_changed(move |editor| {
-            if let Some(text) = get_buffer(&editor) {
-                if let Some(ref current_file) = *current_file.read().unwrap() {
-                    let has_same_sum = current_file.is_same_as(&text.as_bytes());
-                    save_button.set_sensitive(!has_same_sum);
-                }
-            }
+    fn new_document(&self, new_tab: &Button) {
+        let tabs = self.tabs.clone();
+        new_tab.connect_clicked(move |_| {
+            tabs.add();
         });
     }
 
-    fn open_file(&self, current_file: Arc<RwLock<Option<ActiveMetadata>>>) {
-        let editor = self.content.buff.clone();
-        let headerbar = self.header.container.clone();
-        let path_label = self.path_label.clone();
-        let args: Vec<String> = env::args().collect();
-        if args.len() > 1 {
-            open_from_files(&editor, &headerbar, &path_label, &current_file, args[1].clone());
-        }
+    fn open_file(&self, open_button: &Button) {
+        let tabs = self.tabs.clone();
+        let window = self.window.clone();
+        open_button.connect_clicked(move |_| open(&tabs, &window));
+    }
+
+    fn recent_files(&self, recent: &RecentFiles) {
+        // The list is read again each time it opens, so it picks up files
+        // from other windows and programs.
+        let recent_clone = recent.clone();
+        recent.button.connect_toggled(move |button| {
+            if button.get_active() {
+                recent_clone.search_entry.set_text("");
+                recent_clone.refresh();
+                recent_clone.search_entry.grab_focus();
+            }
+        });
+
+        let recent_clone = recent.clone();
+        recent.search_entry.connect_search_changed(move |_| recent_clone.refresh());
 
-        self.header
-            .open
-            .connect_clicked(move |_| open(&editor, &headerbar, &path_label, &current_file));
+        let list = recent.list.clone();
+        recent.search_entry.connect_activate(move |_| {
+            if let Some(row) = list.get_selected_row() {
+                row.activate();
+            }
+        });
+
+        let tabs = self.tabs.clone();
+        let window = self.window.clone();
+        let recent_clone = recent.clone();
+        recent.list.connect_row_activated(move |_, row| {
+            if let Some(path) = recent_clone.path(row) {
+                recent_clone.button.set_active(false);
+                open_path(&tabs, &window, path);
+            }
+        });
+    }
+
+    fn file_drops(&self, window: &Window) {
+        // Each text view handles its own drops; this catches the rest of the
+        // window.
+        open_dropped_files(&self.tabs, window, window);
     }
 
-    fn save_file(&self,save_button: &Button, actual_button: &Button, current_file: Arc<RwLock<Option<ActiveMetadata>>>, save_as: bool) {
-        let editor = self.content.buff.clone();
-        let headerbar = self.header.container.clone();
+    fn save_file(&self, actual_button: &Button, save_as: bool) {
+        let tabs = self.tabs.clone();
         let window = self.window.clone();
-        let save_button = save_button.clone();
-        let path_label = self.path_label.clone();
-        actual_button.connect_clicked(move |_| save(&editor, &headerbar, &window, &path_label, &save_button, &current_file, save_as));
+        actual_button.connect_clicked(move |_| {
+            if let Some(document) = tabs.active() {
+                save(&tabs, &document, &window, save_as);
+            }
+        });
+    }
+
+    fn encoding_changed(&self, encoding_combo: &ComboBoxText) {
+        let tabs = self.tabs.clone();
+        encoding_combo.connect_changed(move |encoding_combo| {
+            let encoding = encoding_combo
+                .get_active_id()
+                .and_then(|id| TextEncoding::from_id(id.as_str()));
+            if let (Some(document), Some(encoding)) = (tabs.active(), encoding) {
+                change_encoding(&tabs, &document, encoding);
+            }
+        });
+    }
+
+    fn line_ending_changed(&self, line_ending_combo: &ComboBoxText) {
+        let tabs = self.tabs.clone();
+        line_ending_combo.connect_changed(move |line_ending_combo| {
+            let line_ending = line_ending_combo
+                .get_active_id()
+                .and_then(|id| LineEnding::from_id(id.as_str()));
+            if let (Some(document), Some(line_ending)) = (tabs.active(), line_ending) {
+                change_line_ending(&tabs, &document, line_ending);
+            }
+        });
+    }
+
+    fn language_changed(&self, language_combo: &ComboBoxText) {
+        let tabs = self.tabs.clone();
+        language_combo.connect_changed(move |language_combo| {
+            if let (Some(document), Some(id)) = (tabs.active(), language_combo.get_active_id()) {
+                document.choose_language(id.as_str());
+            }
+        });
+    }
+
+    fn indentation_changed(&self, status_bar: &StatusBar) {
+        let tabs = self.tabs.clone();
+        status_bar.use_spaces.connect_toggled(move |use_spaces| {
+            if let Some(document) = tabs.active() {
+                let view = &document.content.view;
+                if view.get_insert_spaces_instead_of_tabs() != use_spaces.get_active() {
+                    view.set_insert_spaces_instead_of_tabs(use_spaces.get_active());
+                    tabs.refresh();
+                }
+            }
+        });
+
+        let tabs = self.tabs.clone();
+        status_bar.indent_width.connect_property_value_notify(move |indent_width| {
+            let width = indent_width.get_value_as_int().max(1);
+            if let Some(document) = tabs.active() {
+                let view = &document.content.view;
+                if view.get_tab_width() != width as u32 {
+                    view.set_tab_width(width as u32);
+                    view.set_indent_width(width);
+                    tabs.refresh();
+                }
+            }
+        });
     }
 
     fn font_changed(&self, actual_button: &FontButton) {
-        let view = self.content.view.clone();
+        let tabs = self.tabs.clone();
         let font_button = actual_button.clone();
         let settings = gio::Settings::new("com.github.maze-n.eddit");
         actual_button.connect_font_set(move |_| {
             if let Some(fontname) = font_button.get_font_name() {
-                WidgetExt::override_font(&view, &FontDescription::from_string(fontname.as_str()));
+                for document in tabs.documents() {
+                    WidgetExt::override_font(&document.content.view, &FontDescription::from_string(fontname.as_str()));
+                }
                 settings.set_string("font", fontname.as_str());
             }
         });
     }
 
-    fn set_text_wrap_mode(&self, header: &Header, view: &View) {
+    fn set_text_wrap_mode(&self, header: &Header) {
         let checkbox_wrap_word = header.enable_wrapping_word.clone();
         let checkbox_wrap_char = header.enable_wrapping_char.clone();
+        let settings = gio::Settings::new("com.github.maze-n.eddit");
 
         let checkbox_wrap_char_clone = checkbox_wrap_char.clone();
-        
-        let view_clone = view.clone();
+
+        let tabs = self.tabs.clone();
+        let settings_clone = settings.clone();
         checkbox_wrap_word.connect_toggled(move |checkbox_wrap_word| {
-            if checkbox_wrap_word.get_active() {
-                view_clone.set_wrap_mode(gtk::WrapMode::Word);
+            let wrap_mode = if checkbox_wrap_word.get_active() {
+                gtk::WrapMode::Word
             } else {
                 checkbox_wrap_char_clone.set_active(false);
-                view_clone.set_wrap_mode(gtk::WrapMode::None);
+                gtk::WrapMode::None
+            };
+            for document in tabs.documents() {
+                if !document.is_large() {
+                    document.content.view.set_wrap_mode(wrap_mode);
+                }
             }
+            // New tabs pick their wrap mode up from the settings.
+            settings_clone.set_boolean("text-wrap-word", checkbox_wrap_word.get_active());
         });
 
-        
-        let view_clone = view.clone();
+        let tabs = self.tabs.clone();
         checkbox_wrap_char.connect_toggled(move |checkbox_wrap_char| {
-            if checkbox_wrap_word.get_active() {
-                view_clone.set_wrap_mode(gtk::WrapMode::Word);
+            for document in tabs.documents() {
+                if document.is_large() {
+                    continue;
+                }
+
+                if checkbox_wrap_word.get_active() {
+                    document.content.view.set_wrap_mode(gtk::WrapMode::Word);
+                }
+
+                if checkbox_wrap_char.get_active() {
+                    document.content.view.set_wrap_mode(gtk::WrapMode::Char);
+                }
             }
+            settings.set_boolean("text-wrap-char", checkbox_wrap_char.get_active());
+        });
+    }
+
+    fn autosave_changed(&self, header: &Header) {
+        let settings = gio::Settings::new("com.github.maze-n.eddit");
+
+        let settings_clone = settings.clone();
+        header.enable_autosave.connect_toggled(move |enable_autosave| {
+            settings_clone.set_boolean("autosave", enable_autosave.get_active());
+        });
+
+        header.autosave_interval.connect_property_value_notify(move |autosave_interval| {
+            settings.set_int("autosave-interval", autosave_interval.get_value_as_int());
+        });
+    }
+
+    fn restore_session_changed(&self, restore_session: &CheckButton) {
+        let settings = gio::Settings::new("com.github.maze-n.eddit");
+        restore_session.connect_toggled(move |restore_session| {
+            settings.set_boolean("restore-session", restore_session.get_active());
+        });
+    }
+
+    fn large_file_threshold_changed(&self, large_file_threshold: &SpinButton) {
+        let settings = gio::Settings::new("com.github.maze-n.eddit");
+        large_file_threshold.connect_property_value_notify(move |large_file_threshold| {
+            settings.set_int("large-file-threshold", large_file_threshold.get_value_as_int());
+        });
+    }
+
+    fn recovery_journal(&self) {
+        let tabs = self.tabs.clone();
+        let source = glib::timeout_add_seconds_local(RECOVERY_INTERVAL, move || {
+            tabs.journal();
+            glib::Continue(true)
+        });
 
-            if checkbox_wrap_char.get_active() {
-                view_clone.set_wrap_mode(gtk::WrapMode::Char);
+        // Other windows keep the process running, so the journal of this one
+        // has to stop with it.
+        let source = RefCell::new(Some(source));
+        self.window.connect_destroy(move |_| {
+            if let Some(source) = source.borrow_mut().take() {
+                glib::source_remove(source);
             }
         });
     }
@@ -275,103 +431,138 @@ impl App {
         let replace_button = self.search_bar.replace_button.clone();
         let replace_all = self.search_bar.replace_all_button.clone();
         let replace_entry = self.search_bar.replace_entry.clone();
-        let buff = self.content.buff.clone();
-        let view = self.content.view.clone();
-
-        let search_settings = self.content.search_settings.clone();
-        let search_context = self.content.search_context.clone();
-        let search_flag = TextSearchFlags::CASE_INSENSITIVE;
+        let tabs = self.tabs.clone();
 
-        let search_settings_clone = search_settings.clone();
-        let entry = search_entry.clone();
+        // Opening the search bar picks up the query where it was left, or the
+        // selected text; closing it clears the highlighting.
+        let tabs_clone = tabs.clone();
+        let revealer_clone = revealer.clone();
+        let search_bar = self.search_bar.clone();
         find_button.connect_toggled(move |find_button| {
-            revealer.set_reveal_child(find_button.get_active());
-            entry.grab_focus();
-            search_settings_clone.set_search_text(Some(""));
+            revealer_clone.set_reveal_child(find_button.get_active());
+            if let Some(document) = tabs_clone.active() {
+                if find_button.get_active() {
+                    if search_bar.in_selection.get_active() {
+                        scope_to_selection(&document);
+                    } else {
+                        prefill_search(&document, &search_bar);
+                    }
+                    search_changed(&document, &search_bar);
+                } else {
+                    remember_search(&search_bar);
+                    document.content.search_settings.set_search_text(Some(""));
+                    document.set_search_scope(None);
+                }
+            }
+            search_bar.search_entry.grab_focus();
         });
 
-        let up_clone = up.clone();
         let down_clone = down.clone();
-        let search_settings_clone = search_settings.clone();
-        let buffer = buff.clone();
-        let search_entry_clone = search_entry.clone();
-        search_entry_clone.connect_search_changed(move |entry| {
-            let iter = buffer.get_iter_at_offset(buffer.get_property_cursor_position());
-            if let Some(text) = entry.get_text() {
-                let text = text.as_str();
-                search_settings_clone.set_search_text(Some(text));
-                set_sensitivity(&entry, &up_clone, &down_clone, text, &iter);
+        let search_bar = self.search_bar.clone();
+        search_entry.connect_activate(move |_| {
+            remember_search(&search_bar);
+            down_clone.clicked();
+        });
+
+        let search_bar = self.search_bar.clone();
+        let tabs_clone = tabs.clone();
+        search_entry.connect_search_changed(move |_| {
+            if let Some(document) = tabs_clone.active() {
+                search_changed(&document, &search_bar);
+            }
+        });
+
+        for option in &[&self.search_bar.case_sensitive, &self.search_bar.whole_word, &self.search_bar.regex] {
+            let search_bar = self.search_bar.clone();
+            let tabs_clone = tabs.clone();
+            option.connect_toggled(move |_| {
+                if let Some(document) = tabs_clone.active() {
+                    search_changed(&document, &search_bar);
+                }
+            });
+        }
+
+        let search_bar = self.search_bar.clone();
+        let tabs_clone = tabs.clone();
+        self.search_bar.in_selection.connect_toggled(move |in_selection| {
+            if let Some(document) = tabs_clone.active() {
+                if in_selection.get_active() {
+                    scope_to_selection(&document);
+                }
+                search_changed(&document, &search_bar);
+            }
+        });
+
+        // Each tab keeps its own search context, so carry the query over to
+        // the newly selected document while the search bar is open.
+        let search_bar = self.search_bar.clone();
+        let tabs_clone = tabs.clone();
+        tabs.notebook.connect_property_page_notify(move |_| {
+            if let Some(document) = tabs_clone.active() {
+                if revealer.get_reveal_child() {
+                    search_changed(&document, &search_bar);
+                } else {
+                    document.content.search_settings.set_search_text(Some(""));
+                    document.set_search_scope(None);
+                }
             }
         });
 
         let up_clone = up.clone();
-        let down_clone = down.clone();
-        let buffer = buff.clone();
-        let search_settings_clone = search_settings.clone();
-        let view_clone = view.clone();
+        let tabs_clone = tabs.clone();
         let search_entry_clone = search_entry.clone();
+        let search_bar = self.search_bar.clone();
         down.connect_clicked(move |down| {
-            if let Some(iters) = buffer.get_selection_bounds() {
-                let mut iter = iters.1;
-                if let Some(search_string) = search_settings_clone.get_search_text() {
-                    if let Some(mut match_iters) = iter.forward_search(search_string.as_str(), search_flag, None) {
-                        buffer.select_range(&match_iters.0, &match_iters.1);
-                        view_clone.scroll_to_iter(&mut match_iters.0, 0.0, false, 0.0, 0.0);
-                        iter = match_iters.1;
-                    }
-                    set_sensitivity(&search_entry_clone, &up_clone, &down, search_string.as_str(), &iter);
-                }
-            } else {
-                let mut iter = buffer.get_iter_at_offset(buffer.get_property_cursor_position());
-                if let Some(search_string) = search_settings_clone.get_search_text() {
-                    if let Some(mut match_iters) = iter.forward_search(search_string.as_str(), search_flag, None) {
-                        buffer.select_range(&match_iters.0, &match_iters.1);
-                        view_clone.scroll_to_iter(&mut match_iters.0, 0.0, false, 0.0, 0.0);
-                        iter = match_iters.1;
-                    }
-                    set_sensitivity(&search_entry_clone, &up_clone, &down, search_string.as_str(), &iter);
+            remember_search(&search_bar);
+            if let Some(document) = tabs_clone.active() {
+                let buffer = &document.content.buff;
+                let view = &document.content.view;
+                let mut iter = match buffer.get_selection_bounds() {
+                    Some(iters) => iters.1,
+                    None => buffer.get_iter_at_offset(buffer.get_property_cursor_position()),
+                };
+                if let Some(mut match_iters) = document.next_match(&iter) {
+                    buffer.select_range(&match_iters.0, &match_iters.1);
+                    view.scroll_to_iter(&mut match_iters.0, 0.0, false, 0.0, 0.0);
+                    iter = match_iters.1;
                 }
+                set_sensitivity(&search_entry_clone, &up_clone, &down, &document, &iter);
             }
         });
 
-        let buffer = buff.clone();
+        let down_clone = down.clone();
+        let tabs_clone = tabs.clone();
+        let search_bar = self.search_bar.clone();
         up.connect_clicked(move |up| {
-            if let Some(iters) = buffer.get_selection_bounds() {
-                let mut iter = iters.0;
-                if let Some(search_string) = search_settings.get_search_text() {
-                    if let Some(mut match_iters) = iter.backward_search(search_string.as_str(), search_flag, None) {
-                        buffer.select_range(&match_iters.0, &match_iters.1);
-                        view.scroll_to_iter(&mut match_iters.0, 0.0, false, 0.0, 0.0);
-                        iter = match_iters.0;
-                    }
-                    set_sensitivity(&search_entry, &up, &down_clone, search_string.as_str(), &iter);
-                }
-            } else {
-                let mut iter = buffer.get_iter_at_offset(buffer.get_property_cursor_position());
-                if let Some(search_string) = search_settings.get_search_text() {
-                    if let Some(mut match_iters) = iter.backward_search(search_string.as_str(), search_flag, None) {
-                        buffer.select_range(&match_iters.0, &match_iters.1);
-                        view.scroll_to_iter(&mut match_iters.0, 0.0, false, 0.0, 0.0);
-                        iter = match_iters.0
-                    }
-                    set_sensitivity(&search_entry, &up, &down_clone, search_string.as_str(), &iter);
+            remember_search(&search_bar);
+            if let Some(document) = tabs_clone.active() {
+                let buffer = &document.content.buff;
+                let view = &document.content.view;
+                let mut iter = match buffer.get_selection_bounds() {
+                    Some(iters) => iters.0,
+                    None => buffer.get_iter_at_offset(buffer.get_property_cursor_position()),
+                };
+                if let Some(mut match_iters) = document.previous_match(&iter) {
+                    buffer.select_range(&match_iters.0, &match_iters.1);
+                    view.scroll_to_iter(&mut match_iters.0, 0.0, false, 0.0, 0.0);
+                    iter = match_iters.0;
                 }
+                set_sensitivity(&search_entry, &up, &down_clone, &document, &iter);
             }
         });
 
         let replace_clone = replace_button.clone();
         let replace_all_clone = replace_all.clone();
-        let buffer = buff.clone();
+        let tabs_clone = tabs.clone();
         replace_entry.connect_changed(move |replace_entry| {
+            set_replace_error(replace_entry, None);
             if let Some(text) = replace_entry.get_text() {
-                if text.as_str() != "" {
-                    if let Some(_) = buffer.get_selection_bounds() {
-                        replace_clone.set_sensitive(true);
-                        replace_all_clone.set_sensitive(true);
-                    } else {
-                        replace_clone.set_sensitive(false);
-                        replace_all_clone.set_sensitive(false);
-                    }
+                let has_selection = tabs_clone
+                    .active()
+                    .map_or(false, |document| document.content.buff.get_selection_bounds().is_some());
+                if text.as_str() != "" && has_selection {
+                    replace_clone.set_sensitive(true);
+                    replace_all_clone.set_sensitive(true);
                 } else {
                     replace_clone.set_sensitive(false);
                     replace_all_clone.set_sensitive(false);
@@ -379,49 +570,242 @@ impl App {
             }
         });
 
-        let search_context_clone = search_context.clone();
-        let replace_entry_clone = replace_entry.clone();
+        let search_bar = self.search_bar.clone();
         let down_clone = down.clone();
+        let tabs_clone = tabs.clone();
         replace_button.connect_clicked(move |_| {
-            if let Some(match_selected) = buff.get_selection_bounds() {
-                if let Some(replace_text) = replace_entry_clone.get_text() {
-                    search_context_clone.replace(&match_selected.0, &match_selected.1, replace_text.as_str());
-                    down_clone.clicked();
+            remember_replacement(&search_bar);
+            if let Some(document) = tabs_clone.active() {
+                if let Some(match_selected) = document.content.buff.get_selection_bounds() {
+                    if let Some(replacement) = replacement(&search_bar) {
+                        let context = &document.content.search_context;
+                        match context.replace(&match_selected.0, &match_selected.1, &replacement) {
+                            Ok(()) => down_clone.clicked(),
+                            Err(error) => set_replace_error(&search_bar.replace_entry, Some(&error.to_string())),
+                        }
+                    }
                 }
             }
         });
 
+        let search_bar = self.search_bar.clone();
         replace_all.connect_clicked(move |_| {
-            if let Some(text) = replace_entry.get_text() {
-                search_context.replace_all(text.as_str());
+            remember_replacement(&search_bar);
+            if let Some(document) = tabs.active() {
+                if let Some(replacement) = replacement(&search_bar) {
+                    match replace_all_matches(&document, &replacement) {
+                        Ok(1) => {
+                            document.show_message(MessageType::Info, "Replaced 1 occurrence");
+                        }
+                        Ok(count) => {
+                            document.show_message(MessageType::Info, &format!("Replaced {} occurrences", count));
+                        }
+                        Err(error) => set_replace_error(&search_bar.replace_entry, Some(&error.to_string())),
+                    }
+                }
+            }
+        });
+    }
+
+    fn find_in_files(&self, find_in_files_button: &ToggleButton) {
+        let panel = self.find_in_files.clone();
+        let tabs = self.tabs.clone();
+        find_in_files_button.connect_toggled(move |find_in_files_button| {
+            let active = find_in_files_button.get_active();
+            panel.container.set_visible(active);
+            if !active {
+                panel.cancel();
+                return;
+            }
+
+            // Start from the folder of the active file, or where we were run.
+            if panel.folder.get_filename().is_none() {
+                let folder = tabs
+                    .active()
+                    .and_then(|document| {
+                        let lock = document.current_file.read().unwrap();
+                        lock.as_ref().and_then(|file| file.get_dir())
+                    })
+                    .or_else(|| env::current_dir().ok());
+                if let Some(folder) = folder {
+                    panel.folder.set_filename(&folder);
+                }
+            }
+            panel.search_entry.grab_focus();
+        });
+
+        let search = {
+            let panel = self.find_in_files.clone();
+            let search_bar = self.search_bar.clone();
+            move || {
+                let text = panel.search_entry.get_text().map(|text| text.to_string()).unwrap_or_default();
+                search_bar.search_history.add(&text);
+                panel.search(Query {
+                    text,
+                    case_sensitive: search_bar.case_sensitive.get_active(),
+                    whole_word: search_bar.whole_word.get_active(),
+                    regex: search_bar.regex.get_active(),
+                });
+            }
+        };
+
+        let search_clone = search.clone();
+        self.find_in_files.search_entry.connect_activate(move |_| search_clone());
+
+        let search_clone = search.clone();
+        self.find_in_files.folder.connect_file_set(move |_| search_clone());
+
+        // The panel shares its options with the search bar.
+        for option in &[&self.search_bar.case_sensitive, &self.search_bar.whole_word, &self.search_bar.regex] {
+            let panel = self.find_in_files.clone();
+            let search_clone = search.clone();
+            option.connect_toggled(move |_| {
+                if panel.container.get_visible() {
+                    search_clone();
+                }
+            });
+        }
+
+        let panel = self.find_in_files.clone();
+        self.find_in_files.replace_entry.connect_changed(move |_| panel.refresh_previews());
+
+        let panel = self.find_in_files.clone();
+        let tabs = self.tabs.clone();
+        let window = self.window.clone();
+        let replace_history = self.search_bar.replace_history.clone();
+        self.find_in_files.replace_button.connect_clicked(move |_| {
+            if let Some(text) = panel.replace_entry.get_text() {
+                replace_history.add(text.as_str());
+            }
+            panel.replace(&tabs, &window);
+        });
+
+        let panel = self.find_in_files.clone();
+        let tabs = self.tabs.clone();
+        let window = self.window.clone();
+        self.find_in_files.results.connect_row_activated(move |_, path, _| {
+            if let Some((file, line, column)) = panel.hit_at(path) {
+                open_at(&tabs, &window, file, line, column);
+            }
+        });
+    }
+
+    fn go_to_line(&self, header: &Header) {
+        let tabs = self.tabs.clone();
+        let entry = header.go_to_entry.clone();
+        let message = header.go_to_message.clone();
+        header.go_to_button.connect_toggled(move |go_to_button| {
+            if !go_to_button.get_active() {
+                return;
+            }
+            if let Some(document) = tabs.active() {
+                let lines = document.content.buff.get_line_count();
+                entry.set_placeholder_text(Some(&format!("Line (1 – {})", lines)));
+            }
+            entry.set_text("");
+            message.set_text("");
+            entry.get_style_context().remove_class(&STYLE_CLASS_ERROR);
+            entry.grab_focus();
+        });
+
+        let tabs = self.tabs.clone();
+        let message = header.go_to_message.clone();
+        header.go_to_entry.connect_changed(move |entry| {
+            let target = match (tabs.active(), entry.get_text()) {
+                (Some(document), Some(text)) => go_to_target(&document, text.as_str()),
+                _ => return,
+            };
+            // An empty box is not an error yet, just nothing to go to.
+            match target {
+                Err(ref error) if !error.is_empty() => {
+                    entry.get_style_context().add_class(&STYLE_CLASS_ERROR);
+                    message.set_text(error);
+                }
+                _ => {
+                    entry.get_style_context().remove_class(&STYLE_CLASS_ERROR);
+                    message.set_text("");
+                }
+            }
+        });
+
+        // Clicking the position in the status bar asks where to go too.
+        let go_to_button = header.go_to_button.clone();
+        self.status_bar.position_button.connect_clicked(move |_| {
+            go_to_button.set_active(true);
+        });
+
+        let tabs = self.tabs.clone();
+        let go_to_button = header.go_to_button.clone();
+        header.go_to_entry.connect_activate(move |entry| {
+            if let (Some(document), Some(text)) = (tabs.active(), entry.get_text()) {
+                if let Ok(target) = go_to_target(&document, text.as_str()) {
+                    go_to_button.set_active(false);
+                    document.go_to(target.line - 1, target.column.unwrap_or(1) - 1);
+                }
             }
         });
     }
 
-    fn key_events(&self, current_file: Arc<RwLock<Option<ActiveMetadata>>>) {
-        let editor = self.content.buff.clone();
-        let headerbar = self.header.container.clone();
+    fn key_events(&self) {
+        let tabs = self.tabs.clone();
         let window = self.window.clone();
-        let path_label = self.path_label.clone();
-        let save_button = self.header.save.clone();
         let find_button = self.header.find_button.clone();
+        let find_in_files_button = self.header.find_in_files_button.clone();
+        let go_to_button = self.header.go_to_button.clone();
+        let search_bar = self.search_bar.clone();
 
         self.window.connect_key_press_event(move |_, gdk| {
             match gdk.get_keyval() {
                 key if key == 's' as u32
                     && gdk.get_state().contains(gdk::ModifierType::CONTROL_MASK) =>
                 {
-                    save(&editor, &headerbar, &window, &path_label, &save_button, &current_file, false);
+                    if let Some(document) = tabs.active() {
+                        save(&tabs, &document, &window, false);
+                    }
                 }
                 key if key == 'o' as u32
                     && gdk.get_state().contains(gdk::ModifierType::CONTROL_MASK) =>
                 {
-                    open(&editor, &headerbar, &path_label, &current_file);
+                    open(&tabs, &window);
+                }
+                key if key == 'n' as u32
+                    && gdk.get_state().contains(gdk::ModifierType::CONTROL_MASK) =>
+                {
+                    tabs.add();
+                }
+                key if key == 'N' as u32
+                    && gdk.get_state().contains(gdk::ModifierType::CONTROL_MASK) =>
+                {
+                    if let Some(application) = window.get_application() {
+                        application.activate_action("new-window", None);
+                    }
+                }
+                key if key == 'w' as u32
+                    && gdk.get_state().contains(gdk::ModifierType::CONTROL_MASK) =>
+                {
+                    if let Some(document) = tabs.active() {
+                        tabs.close(&document);
+                    }
+                }
+                key if key == 'l' as u32
+                    && gdk.get_state().contains(gdk::ModifierType::CONTROL_MASK) =>
+                {
+                    go_to_button.set_active(true);
+                }
+                key if key == 'F' as u32
+                    && gdk.get_state().contains(gdk::ModifierType::CONTROL_MASK) =>
+                {
+                    find_in_files_button.set_active(true);
                 }
                 key if key == 'f' as u32
                     && gdk.get_state().contains(gdk::ModifierType::CONTROL_MASK) =>
                 {
-                    find_button.set_active(true);
+                    if !find_button.get_active() {
+                        find_button.set_active(true);
+                    } else if let Some(document) = tabs.active() {
+                        prefill_search(&document, &search_bar);
+                        search_bar.search_entry.grab_focus();
+                    }
                 }
                 key if key == gdk::enums::key::Escape => {
                     find_button.set_active(false);
@@ -434,9 +818,144 @@ impl App {
     }
 }
 
+/// Applies the query and the search bar options to `document`, so the
+/// highlighting and the previous/next buttons all follow the same settings.
+fn search_changed(document: &Document, search_bar: &SearchBox) {
+    let buffer = &document.content.buff;
+    let settings = &document.content.search_settings;
+    let iter = buffer.get_iter_at_offset(buffer.get_property_cursor_position());
+    settings.set_case_sensitive(search_bar.case_sensitive.get_active());
+    settings.set_at_word_boundaries(search_bar.whole_word.get_active());
+    settings.set_regex_enabled(search_bar.regex.get_active());
+    if !search_bar.in_selection.get_active() && document.search_scope().is_some() {
+        document.set_search_scope(None);
+    }
+    if let Some(text) = search_bar.search_entry.get_text() {
+        settings.set_search_text(Some(text.as_str()));
+        document.highlight_scope();
+        set_sensitivity(&search_bar.search_entry, &search_bar.up, &search_bar.down, document, &iter);
+    }
+}
+
+/// Resolves what was typed into the go-to-line box against `document`,
+/// checking a column against the length of its line.
+fn go_to_target(document: &Document, input: &str) -> Result<Target, String> {
+    if document.is_binary() || document.is_loading() {
+        return Err("This document has no lines to go to".to_string());
+    }
+
+    let buffer = &document.content.buff;
+    let current = buffer.get_iter_at_offset(buffer.get_property_cursor_position()).get_line() + 1;
+    let target = goto::parse(input, current, buffer.get_line_count())?;
+    if let Some(column) = target.column {
+        let mut line_end = buffer.get_iter_at_line(target.line - 1);
+        if !line_end.ends_line() {
+            line_end.forward_to_line_end();
+        }
+        let columns = line_end.get_line_offset() + 1;
+        if column > columns {
+            return Err(format!("Line {} has {} columns", target.line, columns));
+        }
+    }
+    Ok(target)
+}
+
+/// Puts the selected text of `document` into the search entry, escaped when
+/// regular expressions are on, as long as it is all on one line.
+fn prefill_search(document: &Document, search_bar: &SearchBox) {
+    let buffer = &document.content.buff;
+    if let Some((start, end)) = buffer.get_selection_bounds() {
+        if start.get_line() == end.get_line() {
+            if let Some(text) = buffer.get_text(&start, &end, false) {
+                if search_bar.regex.get_active() {
+                    search_bar.search_entry.set_text(&regex::escape(text.as_str()));
+                } else {
+                    search_bar.search_entry.set_text(text.as_str());
+                }
+            }
+        }
+    }
+    search_bar.search_entry.select_region(0, -1);
+}
+
+fn remember_search(search_bar: &SearchBox) {
+    if let Some(text) = search_bar.search_entry.get_text() {
+        search_bar.search_history.add(text.as_str());
+    }
+}
+
+fn remember_replacement(search_bar: &SearchBox) {
+    if let Some(text) = search_bar.replace_entry.get_text() {
+        search_bar.replace_history.add(text.as_str());
+    }
+}
+
+/// Confines searching in `document` to its current selection, if it has one.
+/// The range is kept as marks, so selecting matches does not move it.
+fn scope_to_selection(document: &Document) {
+    let selection = document.content.buff.get_selection_bounds();
+    document.set_search_scope(selection.as_ref().map(|(start, end)| (start, end)));
+}
+
+/// The text of the replace entry, read like Find in Files reads it and
+/// written out for GRegex when regular expressions are enabled. Flags the
+/// entry and gives `None` when it cannot be read.
+fn replacement(search_bar: &SearchBox) -> Option<String> {
+    let text = search_bar.replace_entry.get_text()?;
+    if !search_bar.regex.get_active() {
+        return Some(text.to_string());
+    }
+    match Replacement::read(text.as_str()) {
+        Ok(replacement) => Some(replacement.to_gregex()),
+        Err(error) => {
+            set_replace_error(&search_bar.replace_entry, Some(&error));
+            None
+        }
+    }
+}
+
+/// Replaces every match in `document`, or in its search scope, as a single
+/// undoable action, and returns how many were replaced.
+fn replace_all_matches(document: &Document, replacement: &str) -> Result<u32, glib::Error> {
+    let buffer = &document.content.buff;
+    let context = &document.content.search_context;
+    let mut replaced = 0;
+    let mut found = document.first_match();
+
+    buffer.begin_user_action();
+    let result = loop {
+        let (start, end) = match found {
+            Some(iters) => iters,
+            None => break Ok(replaced),
+        };
+        // A mark with right gravity ends up after the inserted replacement,
+        // so the search resumes past it and never matches its own output.
+        let resume = TextMark::new(None, false);
+        buffer.add_mark(&resume, &end);
+        let outcome = context.replace(&start, &end, replacement);
+        let iter = buffer.get_iter_at_mark(&resume);
+        buffer.delete_mark(&resume);
+        if let Err(error) = outcome {
+            break Err(error);
+        }
+        replaced += 1;
+        found = document.next_match(&iter);
+    };
+    buffer.end_user_action();
+    result
+}
+
 impl ConnectedApp {
-    pub fn execute(self) {
+    pub fn window(&self) -> &Window {
+        &self.0.window
+    }
+
+    pub fn tabs(&self) -> &Tabs {
+        &self.0.tabs
+    }
+
+    pub fn show(&self) {
         self.0.window.show_all();
-        gtk::main();
+        self.0.window.present();
     }
-}
\ No newline at end of file
+}
@@ -18,10 +18,12 @@
  */
 
 use gio::SettingsExt;
+use glib::IsA;
 use gtk::*;
 use pango::*;
 use sourceview::*;
 
+#[derive(Clone)]
 pub struct Content {
     pub container: ScrolledWindow,
     pub view: View,
@@ -29,6 +31,7 @@ pub struct Content {
     pub search_settings: SearchSettings,
     pub search_context: SearchContext,
     pub style_manager: StyleSchemeManager,
+    pub hex_view: TextView,
 }
 
 impl Content {
@@ -51,30 +54,67 @@ impl Content {
         if let Some(font) = settings.get_string("font") {
             config_sourceview(&view, font.as_str().to_string(), word_wrap, char_wrap);
         }
-        if let Some(_) = Settings::get_default() {
-            let is_dark = settings.get_boolean("is-dark");
-            if is_dark {
-                style_manager
-                    .get_scheme ("eddit-dark")
-                    .or (style_manager.get_scheme ("Classic"))
-                    .map (|theme| buff.set_style_scheme (Some(&theme)));
-            } else {
-                style_manager
-                    .get_scheme ("eddit-light")
-                    .or (style_manager.get_scheme ("Classic"))
-                    .map (|theme| buff.set_style_scheme (Some(&theme)));
-            }
-        }
-
         container.add(&view);
 
-        Content {
+        let hex_view = TextView::new();
+        hex_view.set_editable(false);
+        hex_view.set_cursor_visible(false);
+        hex_view.set_monospace(true);
+        hex_view.set_left_margin(10);
+
+        let content = Content {
             container,
             buff,
             view,
             search_settings,
             search_context,
             style_manager,
+            hex_view,
+        };
+
+        if let Some(_) = Settings::get_default() {
+            content.set_dark(settings.get_boolean("is-dark"));
+        }
+
+        content
+    }
+
+    /// Swaps the editor for a read-only view of `dump`, for binary files.
+    pub fn show_hex(&self, dump: &str) {
+        if let Some(buffer) = self.hex_view.get_buffer() {
+            buffer.set_text(dump);
+        }
+        self.swap_child(&self.hex_view);
+    }
+
+    /// Brings the editor back after `show_hex`.
+    pub fn show_text(&self) {
+        if let Some(buffer) = self.hex_view.get_buffer() {
+            buffer.set_text("");
+        }
+        self.swap_child(&self.view);
+    }
+
+    fn swap_child<P: IsA<Widget>>(&self, child: &P) {
+        if let Some(current) = self.container.get_child() {
+            self.container.remove(&current);
+        }
+        self.container.add(child);
+        child.show();
+    }
+
+    pub fn set_dark(&self, is_dark: bool) {
+        let buff = &self.buff;
+        if is_dark {
+            self.style_manager
+                .get_scheme ("eddit-dark")
+                .or (self.style_manager.get_scheme ("Classic"))
+                .map (|theme| buff.set_style_scheme (Some(&theme)));
+        } else {
+            self.style_manager
+                .get_scheme ("eddit-light")
+                .or (self.style_manager.get_scheme ("Classic"))
+                .map (|theme| buff.set_style_scheme (Some(&theme)));
         }
     }
 }
@@ -84,6 +124,7 @@ fn config_sourceview(view: &View, font: String, word_wrap: bool, char_wrap: bool
     view.set_show_line_numbers(true);
     view.set_monospace(true);
     view.set_indent_width(4);
+    view.set_tab_width(4);
     view.set_smart_backspace(true);
     view.set_right_margin(10);
     view.set_left_margin(10);
@@ -17,17 +17,23 @@
  * Authored by: mazen <https://github.com/maze-n>
  */
 
+use crate::encoding::{self, TextEncoding};
+use crate::recovery::DiffLine;
 use gtk::*;
 use std::path::PathBuf;
 
 pub struct OpenDialog(FileChooserDialog);
 
-pub struct SaveDialog(FileChooserDialog);
+pub struct SaveDialog(FileChooserDialog, ComboBoxText);
 
 pub struct UnsavedDialog(Dialog);
 
 pub struct ErrorDialog(Dialog);
 
+pub struct RecoveryDialog(Dialog);
+
+pub struct CompareDialog(Dialog);
+
 impl OpenDialog {
     pub fn new(path: Option<PathBuf>) -> OpenDialog {
         let open_dialog = FileChooserDialog::new(
@@ -57,7 +63,7 @@ impl OpenDialog {
 }
 
 impl SaveDialog {
-    pub fn new(path: Option<PathBuf>) -> SaveDialog {
+    pub fn new(path: Option<PathBuf>, encoding: TextEncoding) -> SaveDialog {
         let save_dialog = FileChooserDialog::new(
             Some("Save"),
             Some(&Window::new(WindowType::Popup)),
@@ -69,7 +75,26 @@ impl SaveDialog {
 
         path.map(|p| save_dialog.set_current_folder(p));
 
-        SaveDialog(save_dialog)
+        let encoding_box = Box::new(Orientation::Horizontal, 6);
+        let encoding_label = Label::new(Some("Character encoding:"));
+        let encoding_combo = ComboBoxText::new();
+        for choice in encoding::choices() {
+            encoding_combo.append(Some(&choice.id()), &choice.label());
+        }
+        encoding_combo.set_active_id(Some(&encoding.id()));
+        encoding_box.pack_start(&encoding_label, false, false, 0);
+        encoding_box.pack_start(&encoding_combo, false, false, 0);
+        encoding_box.show_all();
+        save_dialog.set_extra_widget(&encoding_box);
+
+        SaveDialog(save_dialog, encoding_combo)
+    }
+
+    pub fn get_encoding(&self) -> TextEncoding {
+        self.1
+            .get_active_id()
+            .and_then(|id| TextEncoding::from_id(id.as_str()))
+            .unwrap_or_else(TextEncoding::utf8)
     }
 
     pub fn run(&self) -> Option<PathBuf> {
@@ -129,7 +154,7 @@ impl UnsavedDialog {
 }
 
 impl ErrorDialog {
-    pub fn new(window: &Window) -> ErrorDialog {
+    pub fn new(window: &Window, title: &str, message: &str) -> ErrorDialog {
         let error_dialog = Dialog::new_with_buttons(
             Some("Error"),
             Some(window),
@@ -143,9 +168,11 @@ impl ErrorDialog {
         dialog_grid.set_border_width(20);
 
         let error_image = Image::new_from_icon_name(Some("dialog-error"), IconSize::Dialog);
-        let head_label = Label::new(Some("Failed to save the file"));
-        head_label.set_markup("<big><b>Failed to save the file</b></big>");
-        let sub_label = Label::new(Some("The file may be read-only, or another user may have it open. Try saving the file\n with a different name or in a different folder."));
+        let head_label = Label::new(Some(title));
+        head_label.set_markup(&format!("<big><b>{}</b></big>", glib::markup_escape_text(title)));
+        let sub_label = Label::new(Some(message));
+        sub_label.set_line_wrap(true);
+        sub_label.set_max_width_chars(60);
         let label_box = Box::new(Orientation::Vertical, 4);
         label_box.add(&head_label);
         label_box.add(&sub_label);
@@ -165,6 +192,105 @@ impl ErrorDialog {
     }
 }
 
+impl RecoveryDialog {
+    pub fn new(window: &Window, title: &str, diff: Option<Vec<DiffLine>>) -> RecoveryDialog {
+        let recovery_dialog = Dialog::new_with_buttons(
+            Some("Recover"),
+            Some(window),
+            DialogFlags::DESTROY_WITH_PARENT,
+            &[],
+        );
+
+        let dialog_box = recovery_dialog.get_content_area();
+
+        let dialog_grid = Box::new(Orientation::Horizontal, 20);
+        dialog_grid.set_border_width(20);
+
+        let recover_image = Image::new_from_icon_name(Some("document-revert"), IconSize::Dialog);
+        let head_label = Label::new(Some("Recover unsaved changes?"));
+        head_label.set_markup("<big><b>Recover unsaved changes?</b></big>");
+        let sub_label = Label::new(Some(&format!(
+            "eddit was closed unexpectedly while editing\n{}\nThese are the changes compared to the file on disk.",
+            title
+        )));
+        sub_label.set_line_wrap(true);
+
+        let label_box = Box::new(Orientation::Vertical, 4);
+        label_box.add(&head_label);
+        label_box.add(&sub_label);
+
+        dialog_grid.add(&recover_image);
+        dialog_grid.add(&label_box);
+
+        let diff_window = diff_view(diff);
+
+        dialog_box.add(&dialog_grid);
+        dialog_box.pack_start(&diff_window, true, true, 0);
+
+        let restore_button = Button::new_with_label("Restore");
+        restore_button.get_style_context().add_class("suggested-action");
+
+        let later_button = Button::new_with_label("Ask Later");
+        let discard_button = Button::new_with_label("Discard");
+        discard_button.get_style_context().add_class("destructive-action");
+
+        recovery_dialog.add_action_widget(&discard_button, ResponseType::No);
+        recovery_dialog.add_action_widget(&later_button, ResponseType::Cancel);
+        recovery_dialog.add_action_widget(&restore_button, ResponseType::Yes);
+
+        recovery_dialog.show_all();
+
+        RecoveryDialog(recovery_dialog)
+    }
+
+    pub fn run(&self) -> ResponseType {
+        self.0.run()
+    }
+}
+
+impl CompareDialog {
+    pub fn new(window: &Window, title: &str, diff: Option<Vec<DiffLine>>) -> CompareDialog {
+        let compare_dialog = Dialog::new_with_buttons(
+            Some("Compare"),
+            Some(window),
+            DialogFlags::DESTROY_WITH_PARENT,
+            &[("Close", ResponseType::Close)],
+        );
+
+        let dialog_box = compare_dialog.get_content_area();
+
+        let dialog_grid = Box::new(Orientation::Horizontal, 20);
+        dialog_grid.set_border_width(20);
+
+        let compare_image = Image::new_from_icon_name(Some("dialog-information"), IconSize::Dialog);
+        let head_label = Label::new(Some("Changes on disk"));
+        head_label.set_markup("<big><b>Changes on disk</b></big>");
+        let sub_label = Label::new(Some(&format!(
+            "{}\nwas changed by another program. Lines marked - are only in eddit,\nlines marked + are only in the file on disk.",
+            title
+        )));
+        sub_label.set_line_wrap(true);
+
+        let label_box = Box::new(Orientation::Vertical, 4);
+        label_box.add(&head_label);
+        label_box.add(&sub_label);
+
+        dialog_grid.add(&compare_image);
+        dialog_grid.add(&label_box);
+
+        dialog_box.add(&dialog_grid);
+        dialog_box.pack_start(&diff_view(diff), true, true, 0);
+
+        compare_dialog.show_all();
+
+        CompareDialog(compare_dialog)
+    }
+
+    pub fn run(&self) -> ResponseType {
+        self.0.run()
+    }
+}
+
 impl Drop for OpenDialog {
     fn drop(&mut self) {
         self.0.destroy();
@@ -187,4 +313,60 @@ impl Drop for ErrorDialog {
     fn drop(&mut self) {
         self.0.destroy();
     }
-}
\ No newline at end of file
+}
+
+impl Drop for RecoveryDialog {
+    fn drop(&mut self) {
+        self.0.destroy();
+    }
+}
+
+impl Drop for CompareDialog {
+    fn drop(&mut self) {
+        self.0.destroy();
+    }
+}
+
+/// A read-only, colored view of `diff`, with removed lines prefixed by "-"
+/// and added ones by "+".
+fn diff_view(diff: Option<Vec<DiffLine>>) -> ScrolledWindow {
+    let diff_buffer = TextBuffer::new(NONE_TEXT_TAG_TABLE);
+    let added = TextTag::new(Some("added"));
+    added.set_property_foreground(Some("#26a269"));
+    let removed = TextTag::new(Some("removed"));
+    removed.set_property_foreground(Some("#c01c28"));
+    if let Some(tag_table) = diff_buffer.get_tag_table() {
+        tag_table.add(&added);
+        tag_table.add(&removed);
+    }
+
+    match diff {
+        Some(diff) => {
+            for line in diff {
+                let (prefix, text, tag) = match line {
+                    DiffLine::Same(text) => ("  ", text, None),
+                    DiffLine::Removed(text) => ("- ", text, Some(&removed)),
+                    DiffLine::Added(text) => ("+ ", text, Some(&added)),
+                };
+                let start = diff_buffer.get_char_count();
+                diff_buffer.insert(&mut diff_buffer.get_end_iter(), &format!("{}{}\n", prefix, text));
+                if let Some(tag) = tag {
+                    diff_buffer.apply_tag(tag, &diff_buffer.get_iter_at_offset(start), &diff_buffer.get_end_iter());
+                }
+            }
+        }
+        None => diff_buffer.set_text("The file is too large to compare."),
+    }
+
+    let diff_view = TextView::new_with_buffer(&diff_buffer);
+    diff_view.set_editable(false);
+    diff_view.set_cursor_visible(false);
+    diff_view.set_monospace(true);
+
+    let diff_window = ScrolledWindow::new(NONE_ADJUSTMENT, NONE_ADJUSTMENT);
+    diff_window.set_size_request(560, 320);
+    diff_window.set_vexpand(true);
+    diff_window.add(&diff_view);
+
+    diff_window
+}
@@ -18,185 +18,1008 @@
  */
 
 use super::misc::*;
-use super::{OpenDialog, SaveDialog, ErrorDialog};
+use super::recent;
+use super::{Document, Tabs, OpenDialog, SaveDialog, ErrorDialog, RecoveryDialog, CompareDialog};
+use crate::atomic::{self, Written};
+use crate::cli::{FileArg, Options};
+use crate::encoding::{self, TextEncoding};
+use crate::error::FileError;
+use crate::hex;
+use crate::line_ending::{self, LineEnding};
+use crate::recovery::{self, Snapshot};
+use crate::session::{self, DocumentState, Session};
 use crate::state::ActiveMetadata;
+use gio::{FileExt, SettingsExt};
+use glib::{Cast, IsA, ObjectExt};
+use gtk::prelude::{DragContextExtManual, WidgetExtManual};
 use gtk::*;
-use sourceview::*;
-use std::fs::File;
-use std::fs::OpenOptions;
-use std::io::{self, Read, Write};
-use std::path::PathBuf;
-use std::sync::RwLock;
-use faccess::PathExt;
+use sourceview::BufferExt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// Files above this size are refused; a text buffer cannot address more
+/// than 2^31 characters, and gets unusably slow well before that.
+const MAX_FILE_SIZE: u64 = 1 << 30;
+
+/// How many bytes are read, or inserted into the buffer, per step of loading.
+const LOAD_CHUNK: usize = 1 << 20;
+
+/// The drag and drop target for a list of files.
+const URI_LIST: &str = "text/uri-list";
 
 pub enum SaveAction {
-    New(ActiveMetadata),
-    Saved,
+    New(ActiveMetadata, Written),
+    Saved(Vec<u8>, Written),
+    Conflict,
     Canceled,
 }
 
-pub fn save(
-    editor: &Buffer,
-    headerbar: &HeaderBar,
-    window: &Window,
-    path_label: &Label,
-    save: &Button,
-    current_file: &RwLock<Option<ActiveMetadata>>,
-    save_as: bool,
-) {
-    if let Some(text) = get_buffer(editor) {
-        let result = if save_as {
-            write_data(None, text.as_bytes())
-        } else {
-            write_data(current_file.read().unwrap().as_ref(), text.as_bytes())
-        };
+enum LoadMessage {
+    Progress(f64),
+    Loaded(Loaded),
+    Binary(Vec<u8>),
+    Failed(FileError),
+    Canceled,
+}
 
-        match result {
-            Ok(SaveAction::New(file)) => {
-                path_label.set_text(&file.get_path().to_string_lossy());
-                if let Some(filename) = file.get_path().file_name() {
-                    headerbar.set_subtitle(Some(&filename.to_string_lossy()));
-                }
+/// A file read and decoded off the main thread, ready to be shown.
+struct Loaded {
+    file: ActiveMetadata,
+    contents: String,
+    mixed: bool,
+}
 
-                let mut current_file = current_file.write().unwrap();
-                *current_file = Some(file);
-                save.set_sensitive(false);
-            }
+pub fn save(tabs: &Tabs, document: &Document, window: &Window, save_as: bool) {
+    if document.is_loading() || document.is_binary() || (document.is_read_only() && !save_as) {
+        return;
+    }
+
+    if let Some(text) = get_buffer(&document.content.buff) {
+        let result = write_data(document.current_file.read().unwrap().as_ref(), save_as, text.as_str());
 
-            Ok(SaveAction::Saved) => {
-                if let Some(ref mut current_file) = *current_file.write().unwrap() {
-                    current_file.set_sum(&text.as_bytes());
-                    save.set_sensitive(false);
-                }
+        match result {
+            Ok(action) => {
+                finish_save(tabs, document, window, &text, action);
             }
+            Err(error) => {
+                let error_dialog = ErrorDialog::new(&window, "Failed to save the file", &error.to_string());
+                let _ = error_dialog.run();
+            }
+        }
+        tabs.refresh();
+    }
+}
 
-            Ok(SaveAction::Canceled) => (),
+/// Saves `document` in place without asking anything, for autosave. Failures
+/// are reported above the text instead of in a dialog.
+pub fn autosave(tabs: &Tabs, document: &Document, window: &Window) {
+    if !document.is_modified() {
+        return;
+    }
 
-            _ => {
-                let error_dialog = ErrorDialog::new(&window);
-                let _ = error_dialog.run();
+    if let Some(text) = get_buffer(&document.content.buff) {
+        let result = match *document.current_file.read().unwrap() {
+            Some(ref file) => write_data(Some(file), false, text.as_str()),
+            None => return,
+        };
+
+        match result {
+            Ok(action) => {
+                finish_save(tabs, document, window, &text, action);
+            }
+            Err(error) => {
+                document.show_message(MessageType::Error, &format!("Autosave failed: {}", error));
             }
         }
+        tabs.refresh();
     }
 }
 
-fn write_data(path: Option<&ActiveMetadata>, data: &[u8]) -> io::Result<SaveAction> {
-    if let Some(path) = path {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(path.get_path())?;
-        file.write_all(&data)?;
-        return Ok(SaveAction::Saved);
+/// Writes `data` in the document's encoding and line ending. Without a file, or for "save
+/// as", the user picks the path and encoding first. A file that another program changed
+/// since it was loaded is left alone.
+fn write_data(current_file: Option<&ActiveMetadata>, save_as: bool, data: &str) -> Result<SaveAction, FileError> {
+    if let Some(path) = current_file {
+        if !save_as {
+            if path.get_path().exists() && !path.is_unchanged_on_disk() {
+                return Ok(SaveAction::Conflict);
+            }
+            let error = |error| FileError::new(path.get_path(), error);
+            let data = path.get_encoding().encode(&path.get_line_ending().apply(data)).map_err(error)?;
+            let written = atomic::write(path.get_path(), &data).map_err(error)?;
+            return Ok(SaveAction::Saved(data, written));
+        }
     }
 
-    let save_dialog = SaveDialog::new(None);
+    let save_dialog = SaveDialog::new(
+        current_file.and_then(|path| path.get_dir()),
+        current_file.map_or_else(TextEncoding::utf8, |path| path.get_encoding()),
+    );
     if let Some(new_path) = save_dialog.run() {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&new_path)?;
-        file.write_all(&data)?;
-        Ok(SaveAction::New(ActiveMetadata::new(new_path, data)))
+        let encoding = save_dialog.get_encoding();
+        let line_ending = current_file.map_or(LineEnding::Lf, |path| path.get_line_ending());
+        let error = |error| FileError::new(&new_path, error);
+        let encoded = encoding.encode(&line_ending.apply(data)).map_err(error)?;
+        let written = atomic::write(&new_path, &encoded).map_err(error)?;
+        let mut file = ActiveMetadata::new(new_path, data.as_bytes(), encoding, line_ending);
+        file.set_disk_state(&encoded);
+        Ok(SaveAction::New(file, written))
     } else {
         Ok(SaveAction::Canceled)
     }
 }
 
-pub fn save_before_close(
-    editor: &Buffer,
-    window: &Window,
-    save: &Button,
-    current_file: &RwLock<Option<ActiveMetadata>>,
-) -> bool
-{
+/// Records the outcome of writing `text` from `document`. Returns whether the
+/// document ended up saved.
+fn finish_save(tabs: &Tabs, document: &Document, window: &Window, text: &str, action: SaveAction) -> bool {
+    let written = match action {
+        SaveAction::New(file, written) => {
+            *document.current_file.write().unwrap() = Some(file);
+            tabs.watch(document);
+            document.guess_language();
+            written
+        }
+
+        SaveAction::Saved(data, written) => {
+            if let Some(ref mut current_file) = *document.current_file.write().unwrap() {
+                current_file.set_sum(&text.as_bytes());
+                current_file.set_disk_state(&data);
+            }
+            written
+        }
+
+        SaveAction::Conflict => {
+            show_disk_change(
+                tabs,
+                document,
+                window,
+                "The file was changed by another program, so it was not saved. \
+                 Reload it, or keep your version and save again to overwrite it.",
+            );
+            return false;
+        }
+
+        SaveAction::Canceled => return false,
+    };
+
+    document.hide_message();
+    document.set_clean();
+    if let Some(path) = document.path() {
+        if let Written::InPlace(fallback) = written {
+            document.show_message(MessageType::Warning, &fallback.describe(&path));
+        }
+        recent::remember(&path);
+    }
+    true
+}
+
+pub fn save_before_close(tabs: &Tabs, document: &Document, window: &Window) -> bool {
     let mut is_saved = false;
-    if let Some(text) = get_buffer(editor) {
-        let result = write_data(current_file.read().unwrap().as_ref(), text.as_bytes());
+    if let Some(text) = get_buffer(&document.content.buff) {
+        let result = write_data(document.current_file.read().unwrap().as_ref(), false, text.as_str());
 
         match result {
-            Ok(SaveAction::New(file)) => {
-                let mut current_file = current_file.write().unwrap();
-                *current_file = Some(file);
-                save.set_sensitive(false);
-                is_saved = true;
-            }
+            Ok(action) => is_saved = finish_save(tabs, document, window, &text, action),
 
-            Ok(SaveAction::Saved) => {
-                if let Some(ref mut current_file) = *current_file.write().unwrap() {
-                    current_file.set_sum(&text.as_bytes());
-                    save.set_sensitive(false);
-                }
-                is_saved = true;
+            Err(error) => {
+                let error_dialog = ErrorDialog::new(&window, "Failed to save the file", &error.to_string());
+                let _ = error_dialog.run();
             }
+        }
+    }
+    is_saved
+}
+
+/// Warns above the text when the file behind `document` was changed or
+/// removed by another program since it was last loaded or saved.
+pub fn check_disk(tabs: &Tabs, document: &Document, window: &Window) {
+    let exists = match *document.current_file.read().unwrap() {
+        Some(ref file) if !file.is_unchanged_on_disk() => file.get_path().exists(),
+        _ => return,
+    };
+
+    if exists {
+        show_disk_change(tabs, document, window, "The file was changed by another program.");
+    } else {
+        document.show_message(
+            MessageType::Warning,
+            "The file was deleted or moved by another program. Saving will create it again.",
+        );
+    }
+}
+
+/// Shows `message` about a change on disk, offering to reload the file, to
+/// keep the text in the editor or to compare the two.
+fn show_disk_change(tabs: &Tabs, document: &Document, window: &Window, message: &str) {
+    let info_bar = document.show_message(MessageType::Warning, message);
+    info_bar.add_button("Reload", ResponseType::Accept);
+    info_bar.add_button("Keep Mine", ResponseType::Reject);
+    info_bar.add_button("Compare", ResponseType::Other(0));
+
+    let tabs = tabs.clone();
+    let document = document.clone();
+    let window = window.clone();
+    info_bar.connect_response(move |_, response| match response {
+        ResponseType::Accept => reload(&tabs, &document),
+        ResponseType::Reject => keep_mine(&tabs, &document),
+        ResponseType::Other(0) => compare(&document, &window),
+        _ => (),
+    });
+}
+
+/// Replaces the text of `document` with the file on disk, in its current
+/// encoding when the file is still valid in it.
+fn reload(tabs: &Tabs, document: &Document) {
+    let (path, encoding) = match *document.current_file.read().unwrap() {
+        Some(ref file) => (file.get_path().to_path_buf(), file.get_encoding()),
+        None => return,
+    };
+
+    match read_file(&path) {
+        Ok(data) => {
+            let encoding = match encoding.decode(&data) {
+                Some(_) => encoding,
+                None => encoding::detect(&data),
+            };
+            let contents = encoding.decode(&data).unwrap_or_default();
+            set_contents(tabs, document, path, &data, contents, encoding);
+        }
+        Err(error) => {
+            document.show_message(MessageType::Error, &format!("Could not reload the file: {}", error));
+        }
+    }
+}
 
-            Ok(SaveAction::Canceled) => (),
+/// Accepts the file on disk as it is now, so the text in the editor counts as
+/// modified against it and the next save overwrites it.
+fn keep_mine(tabs: &Tabs, document: &Document) {
+    if let Some(ref mut file) = *document.current_file.write().unwrap() {
+        let data = fs::read(file.get_path()).unwrap_or_default();
+        let contents = file.get_encoding().decode(&data).unwrap_or_default();
+        file.set_text_sum(LineEnding::Lf.apply(&contents).as_bytes());
+        file.set_disk_state(&data);
+    }
+    document.hide_message();
+    document.content.buff.set_modified(true);
+    tabs.refresh();
+}
 
-            _ => {
-                let error_dialog = ErrorDialog::new(&window);
+/// Shows how the text in the editor differs from the file on disk.
+fn compare(document: &Document, window: &Window) {
+    let path = match *document.current_file.read().unwrap() {
+        Some(ref file) => file.get_path().to_path_buf(),
+        None => return,
+    };
+
+    if let Some(text) = get_buffer(&document.content.buff) {
+        let on_disk = read_normalized(&path).unwrap_or_default();
+        let dialog = CompareDialog::new(window, &path.to_string_lossy(), recovery::diff(&text, &on_disk));
+        let _ = dialog.run();
+    }
+}
+
+pub fn open(tabs: &Tabs, window: &Window) {
+    let open_dialog = OpenDialog::new(match tabs.active() {
+        Some(document) => {
+            let lock = document.current_file.read().unwrap();
+            lock.as_ref().and_then(|path| path.get_dir())
+        }
+        None => None,
+    });
+
+    if let Some(new_file) = open_dialog.run() {
+        open_path(tabs, window, new_file);
+    }
+}
+
+/// Opens what the command line asked for: every file, at its position, and
+/// then `stdin`, the text piped to the command if it read any. With
+/// `--wait`, `finished` runs once the last of those documents is closed, or
+/// right away if none could be opened, so whoever started the command can
+/// carry on.
+pub fn open_from_command_line<F: Fn() + 'static>(
+    tabs: &Tabs,
+    window: &Window,
+    options: &Options,
+    stdin: Option<io::Result<Vec<u8>>>,
+    finished: F,
+) {
+    // Counts the documents `--wait` waits for, plus one until they have all
+    // been counted, so that `finished` neither runs early nor never runs
+    // when nothing could be opened.
+    let waiting = Arc::new(AtomicUsize::new(1));
+    let finished: Rc<dyn Fn()> = Rc::new(finished);
+
+    for file in &options.files {
+        let FileArg { path, line, column } = file.clone();
+        let tabs_clone = tabs.clone();
+        let options_clone = options.clone();
+        let result = load_file(tabs, window, path, move |document| {
+            if let Some(encoding) = options_clone.encoding {
+                change_encoding(&tabs_clone, document, encoding);
+            }
+            if options_clone.readonly {
+                document.set_read_only(true);
+                tabs_clone.refresh();
+            }
+            if let Some(line) = line {
+                document.go_to(line - 1, column.unwrap_or(1) - 1);
+            }
+        });
+        // A file that fails to load later closes its tab, which counts too.
+        match result {
+            Ok(document) if options.wait => wait_for(&document, &waiting, &finished),
+            Ok(_) => (),
+            Err(error) => {
+                let error_dialog = ErrorDialog::new(window, "Failed to open the file", &error.to_string());
                 let _ = error_dialog.run();
             }
         }
     }
-    is_saved
+
+    match stdin {
+        Some(Ok(data)) => open_stdin(tabs, options, &data, &waiting, &finished),
+        Some(Err(error)) => {
+            let error_dialog = ErrorDialog::new(window, "Failed to read standard input", &error.to_string());
+            let _ = error_dialog.run();
+        }
+        None => (),
+    }
+
+    if options.wait && waiting.fetch_sub(1, Ordering::SeqCst) == 1 {
+        finished();
+    }
+}
+
+/// Shows the text read from standard input in a new document.
+fn open_stdin(tabs: &Tabs, options: &Options, data: &[u8], waiting: &Arc<AtomicUsize>, finished: &Rc<dyn Fn()>) {
+    let encoding = options.encoding.unwrap_or_else(|| encoding::detect(data));
+    let (contents, _) = encoding.decode_lossy(data);
+    let document = tabs.target();
+    let buff = &document.content.buff;
+    buff.begin_not_undoable_action();
+    buff.set_text(&contents);
+    buff.end_not_undoable_action();
+    buff.place_cursor(&buff.get_start_iter());
+    document.guess_language();
+    document.set_read_only(options.readonly);
+    if options.wait {
+        wait_for(&document, waiting, finished);
+    }
+    tabs.refresh();
+}
+
+/// Counts `document` among those `--wait` waits for, running `finished`
+/// once the last of them is closed.
+fn wait_for(document: &Document, waiting: &Arc<AtomicUsize>, finished: &Rc<dyn Fn()>) {
+    waiting.fetch_add(1, Ordering::SeqCst);
+    let waiting = waiting.clone();
+    let finished = finished.clone();
+    document.connect_closed(move || {
+        if waiting.fetch_sub(1, Ordering::SeqCst) == 1 {
+            finished();
+        }
+    });
 }
 
-pub fn open(editor: &Buffer, headerbar: &HeaderBar, path_label: &Label, current_file: &RwLock<Option<ActiveMetadata>>) {
-    let open_dialog = OpenDialog::new({
-        let lock = current_file.read().unwrap();
-        if let Some(ref path) = *lock {
-            path.get_dir()
+pub fn open_path(tabs: &Tabs, window: &Window, path: PathBuf) {
+    if let Err(error) = load_file(tabs, window, path, |_| ()) {
+        let error_dialog = ErrorDialog::new(window, "Failed to open the file", &error.to_string());
+        let _ = error_dialog.run();
+    }
+}
+
+/// Opens the files dropped onto `widget` in tabs of their own, instead of
+/// the text view inserting their URIs. Text dragged from elsewhere is still
+/// inserted as before.
+pub fn open_dropped_files<W: IsA<Widget>>(tabs: &Tabs, window: &Window, widget: &W) {
+    let uri_list = gdk::Atom::intern(URI_LIST);
+    match widget.drag_dest_get_target_list() {
+        Some(targets) => targets.add_uri_targets(0),
+        None => widget.drag_dest_set(
+            DestDefaults::MOTION | DestDefaults::HIGHLIGHT,
+            &[TargetEntry::new(URI_LIST, TargetFlags::OTHER_APP, 0)],
+            gdk::DragAction::COPY,
+        ),
+    }
+
+    // File managers offer both the URIs and their text, and the text view
+    // would pick the text, so the URIs are asked for first.
+    widget.connect_drag_drop(move |widget, context, _, _, time| {
+        if context.list_targets().contains(&uri_list) {
+            widget.drag_get_data(context, &uri_list, time);
+            Inhibit(true)
         } else {
-            None
+            Inhibit(false)
         }
     });
 
-    if let Some(new_file) = open_dialog.run() {
-        if let Ok(mut file) = File::open(&new_file) {
-            let mut contents = String::new();
-            let _ = file.read_to_string(&mut contents);
+    let tabs = tabs.clone();
+    let window = window.clone();
+    widget.connect_drag_data_received(move |widget, context, _, _, data, _, time| {
+        if data.get_target() != uri_list {
+            return;
+        }
+        widget.stop_signal_emission("drag-data-received");
+
+        let paths: Vec<PathBuf> = data
+            .get_uris()
+            .iter()
+            .filter_map(|uri| gio::File::new_for_uri(uri).get_path())
+            .collect();
+        if paths.is_empty() {
+            // Links from a browser are no files to open; the text view can
+            // still take them as text.
+            let text = context.list_targets().into_iter().find(|&target| {
+                target != uri_list && targets_include_text(&[&target])
+            });
+            match text {
+                Some(text) if widget.is::<TextView>() => widget.drag_get_data(context, &text, time),
+                _ => context.drag_finish(false, false, time),
+            }
+            return;
+        }
+
+        context.drag_finish(true, false, time);
+        for path in paths {
+            open_path(&tabs, &window, path);
+        }
+    });
+}
+
+/// Opens `path`, or switches to the tab that has it, and puts the cursor on
+/// `line` at `column`, both counted from 0.
+pub fn open_at(tabs: &Tabs, window: &Window, path: PathBuf, line: i32, column: i32) {
+    if let Err(error) = load_file(tabs, window, path, move |document| document.go_to(line, column)) {
+        let error_dialog = ErrorDialog::new(window, "Failed to open the file", &error.to_string());
+        let _ = error_dialog.run();
+    }
+}
+
+/// Loads `new_file` into its own tab in the background, or switches to the
+/// tab that already has it open, and returns that tab. `then` runs once the
+/// text is in place; a tab whose file fails to load is closed instead.
+fn load_file<F: FnOnce(&Document) + 'static>(
+    tabs: &Tabs,
+    window: &Window,
+    new_file: PathBuf,
+    then: F,
+) -> Result<Document, FileError> {
+    if let Some(document) = tabs.find(&new_file) {
+        tabs.present(&document);
+        if !document.is_loading() {
+            then(&document);
+        }
+        return Ok(document);
+    }
+
+    let size = check_file(&new_file)?;
+    let document = tabs.target();
+    let settings = gio::Settings::new("com.github.maze-n.eddit");
+    if size > settings.get_int("large-file-threshold").max(1) as u64 * 1024 * 1024 {
+        document.enable_large_mode();
+    }
+    let canceled = document.start_loading(&new_file);
+
+    let name = new_file.file_name().unwrap_or_else(|| new_file.as_os_str()).to_string_lossy().into_owned();
+    let info_bar = document.show_message(MessageType::Info, &format!("Opening {}…", name));
+    info_bar.set_show_close_button(false);
+    let progress = ProgressBar::new();
+    progress.set_show_text(true);
+    progress.set_text(Some("Reading"));
+    progress.set_valign(Align::Center);
+    if let Some(area) = info_bar.get_content_area().and_then(|area| area.downcast::<Box>().ok()) {
+        area.pack_start(&progress, true, true, 0);
+    }
+    info_bar.add_button("Cancel", ResponseType::Cancel);
+    info_bar.show_all();
+    let canceled_clone = canceled.clone();
+    info_bar.connect_response(move |_, response| {
+        if response == ResponseType::Cancel {
+            canceled_clone.store(true, Ordering::SeqCst);
+        }
+    });
 
-            if new_file.writable() {
-                path_label.set_text(&new_file.to_string_lossy());
-            } else {
-                path_label.set_text(&format!("{} - [READ ONLY]", &new_file.to_string_lossy()));
+    let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    let path = new_file.clone();
+    let canceled_clone = canceled.clone();
+    thread::spawn(move || {
+        let message = read_in_chunks(path, size, &canceled_clone, &sender);
+        let _ = sender.send(message);
+    });
+
+    let tabs = tabs.clone();
+    let window = window.clone();
+    let mut then = Some(then);
+    receiver.attach(None, move |message| {
+        match message {
+            LoadMessage::Progress(fraction) => {
+                progress.set_fraction(fraction);
+                return glib::Continue(true);
+            }
+            LoadMessage::Loaded(loaded) => {
+                insert_in_chunks(&tabs, &document, loaded, &progress, canceled.clone(), then.take());
             }
-            if let Some(filename) = new_file.file_name() {
-                headerbar.set_subtitle(Some(&filename.to_string_lossy()));
+            LoadMessage::Binary(data) => show_binary(&tabs, &document, new_file.clone(), &data),
+            LoadMessage::Failed(error) => {
+                abandon(&tabs, &document);
+                let error_dialog = ErrorDialog::new(&window, "Failed to open the file", &error.to_string());
+                let _ = error_dialog.run();
             }
+            LoadMessage::Canceled => abandon(&tabs, &document),
+        }
+        glib::Continue(false)
+    });
+
+    Ok(())
+}
 
-            *current_file.write().unwrap() = Some(ActiveMetadata::new(new_file, &contents.as_bytes()));
-            editor.set_text(&contents);
-            editor.place_cursor(&editor.get_start_iter());
+/// Checks that `path` is a file small enough to edit, returning its size.
+fn check_file(path: &Path) -> Result<u64, FileError> {
+    let metadata = fs::metadata(path).map_err(|error| FileError::new(path, error))?;
+    if metadata.is_dir() {
+        return Err(FileError::is_directory(path));
+    }
+    if metadata.len() > MAX_FILE_SIZE {
+        return Err(FileError::too_large(path, metadata.len(), MAX_FILE_SIZE));
+    }
+    Ok(metadata.len())
+}
+
+/// Reads the whole of `path`, refusing folders and files too large to edit.
+fn read_file(path: &Path) -> Result<Vec<u8>, FileError> {
+    check_file(path)?;
+    fs::read(path).map_err(|error| FileError::new(path, error))
+}
+
+/// Reads and decodes `path` on a worker thread, reporting progress after
+/// every chunk until `canceled` is set.
+fn read_in_chunks(path: PathBuf, size: u64, canceled: &AtomicBool, sender: &glib::Sender<LoadMessage>) -> LoadMessage {
+    let mut file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(error) => return LoadMessage::Failed(FileError::new(&path, error)),
+    };
+
+    let mut data = Vec::with_capacity(size as usize);
+    let mut chunk = vec![0; LOAD_CHUNK];
+    loop {
+        if canceled.load(Ordering::SeqCst) {
+            return LoadMessage::Canceled;
         }
+        match file.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(length) => {
+                data.extend_from_slice(&chunk[..length]);
+                let _ = sender.send(LoadMessage::Progress(data.len() as f64 / size.max(1) as f64));
+            }
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => (),
+            Err(error) => return LoadMessage::Failed(FileError::new(&path, error)),
+        }
+    }
+
+    if encoding::looks_binary(&data) {
+        return LoadMessage::Binary(data);
     }
+
+    let encoding = encoding::detect(&data);
+    let contents = encoding.decode(&data).unwrap_or_default();
+    LoadMessage::Loaded(prepare(path, &data, contents, encoding))
 }
 
-pub fn open_from_files(
-    editor: &Buffer,
-    headerbar: &HeaderBar,
-    path_label: &Label,
-    current_file: &RwLock<Option<ActiveMetadata>>,
-    path: String,
+/// Moves the text of `loaded` into `document` a chunk per main loop
+/// iteration, so the window keeps responding, then hands the file over.
+fn insert_in_chunks<F: FnOnce(&Document) + 'static>(
+    tabs: &Tabs,
+    document: &Document,
+    loaded: Loaded,
+    progress: &ProgressBar,
+    canceled: Arc<AtomicBool>,
+    then: Option<F>,
 ) {
-    let new_file = PathBuf::from(path);
-    if let Ok(mut file) = File::open(&new_file) {
-        let mut contents = String::new();
-        let _ = file.read_to_string(&mut contents);
+    let buff = document.content.buff.clone();
+    buff.begin_not_undoable_action();
+    progress.set_text(Some("Loading"));
+    progress.set_fraction(0.0);
+
+    let tabs = tabs.clone();
+    let document = document.clone();
+    let progress = progress.clone();
+    let mut offset = 0;
+    let mut pending = Some((loaded, then));
+    glib::idle_add_local(move || {
+        if canceled.load(Ordering::SeqCst) {
+            buff.set_text("");
+            buff.end_not_undoable_action();
+            abandon(&tabs, &document);
+            return glib::Continue(false);
+        }
+
+        if let Some((ref loaded, _)) = pending {
+            let contents = &loaded.contents;
+            let mut end = (offset + LOAD_CHUNK).min(contents.len());
+            while !contents.is_char_boundary(end) {
+                end += 1;
+            }
+            buff.insert(&mut buff.get_end_iter(), &contents[offset..end]);
+            offset = end;
+            progress.set_fraction(offset as f64 / contents.len().max(1) as f64);
+            if offset < contents.len() {
+                return glib::Continue(true);
+            }
+        }
+
+        buff.end_not_undoable_action();
+        if let Some((loaded, then)) = pending.take() {
+            *document.current_file.write().unwrap() = Some(loaded.file);
+            document.finish_loading();
+            finish_load(&tabs, &document, loaded.mixed);
+            if let Some(then) = then {
+                then(&document);
+            }
+        }
+        glib::Continue(false)
+    });
+}
+
+/// Shows the start of the binary file `path` as a read-only hex dump, and
+/// offers to open it as text in a chosen encoding instead.
+fn show_binary(tabs: &Tabs, document: &Document, path: PathBuf, data: &[u8]) {
+    document.finish_loading();
+    let (dump, cut) = hex::dump_start(data);
+    document.set_binary(Some(path.clone()), &dump);
+
+    let message = if cut {
+        format!(
+            "This file looks binary. Its first {} MB are shown as hex, read-only.",
+            hex::VIEW_LIMIT >> 20
+        )
+    } else {
+        String::from("This file looks binary, so it is shown as hex, read-only.")
+    };
+    let info_bar = document.show_message(MessageType::Info, &message);
+
+    let encoding_combo = ComboBoxText::new();
+    for choice in encoding::choices() {
+        encoding_combo.append(Some(&choice.id()), &choice.label());
+    }
+    encoding_combo.set_active_id(Some(&encoding::detect(data).id()));
+    if let Some(area) = info_bar.get_content_area().and_then(|area| area.downcast::<Box>().ok()) {
+        area.pack_start(&encoding_combo, false, false, 0);
+    }
+    info_bar.add_button("Open as Text", ResponseType::Accept);
+    info_bar.show_all();
+
+    let tabs = tabs.clone();
+    let document = document.clone();
+    info_bar.connect_response(move |_, response| {
+        let encoding = encoding_combo
+            .get_active_id()
+            .and_then(|id| TextEncoding::from_id(id.as_str()))
+            .unwrap_or_else(TextEncoding::utf8);
+        if response == ResponseType::Accept {
+            open_as_text(&tabs, &document, &path, encoding);
+        }
+    });
+    tabs.refresh();
+}
+
+/// Opens the binary file `path` in the editor after all, replacing whatever
+/// is not valid in `encoding`.
+fn open_as_text(tabs: &Tabs, document: &Document, path: &Path, encoding: TextEncoding) {
+    let data = match read_file(path) {
+        Ok(data) => data,
+        Err(error) => {
+            document.show_message(MessageType::Error, &format!("Could not open the file as text: {}", error));
+            return;
+        }
+    };
+
+    let (contents, replaced) = encoding.decode_lossy(&data);
+    document.set_binary(None, "");
+    set_contents(tabs, document, path.to_path_buf(), &data, contents, encoding);
+    if replaced {
+        document.show_message(
+            MessageType::Warning,
+            &format!(
+                "Some bytes are not valid {} and are shown as replacement characters. \
+                 Saving the file will write those instead of the original bytes.",
+                encoding.label()
+            ),
+        );
+    }
+}
+
+/// Gives up on a load that failed or was canceled, closing its tab.
+fn abandon(tabs: &Tabs, document: &Document) {
+    document.finish_loading();
+    tabs.close(document);
+}
+
+/// Reads `path` the way it would be shown in the editor, for comparing
+/// against a recovered snapshot.
+fn read_normalized(path: &Path) -> Option<String> {
+    let data = fs::read(path).ok()?;
+    let contents = encoding::detect(&data).decode(&data)?;
+    Some(LineEnding::Lf.apply(&contents))
+}
+
+/// Offers to restore the documents a crashed or killed session left behind
+/// in the recovery journal, one at a time.
+pub fn recover(tabs: &Tabs, window: &Window) {
+    for orphan in recovery::orphans() {
+        let snapshot = &orphan.snapshot;
+        let on_disk = snapshot.path.as_ref().and_then(|path| read_normalized(path)).unwrap_or_default();
+        let title = match snapshot.path {
+            Some(ref path) => path.to_string_lossy().into_owned(),
+            None => String::from("New file"),
+        };
+
+        let dialog = RecoveryDialog::new(window, &title, recovery::diff(&on_disk, &snapshot.text));
+        let result = dialog.run();
+        if result == ResponseType::Yes.into() {
+            restore(tabs, window, snapshot);
+            recovery::remove_orphan(&orphan);
+        } else if result == ResponseType::No.into() {
+            recovery::remove_orphan(&orphan);
+        }
+    }
+}
+
+/// Opens the snapshot's file, if it still exists, and replaces its text with
+/// the recovered one, leaving the document modified.
+fn restore(tabs: &Tabs, window: &Window, snapshot: &Snapshot) {
+    let text = snapshot.text.clone();
+    let tabs_clone = tabs.clone();
+    let fill = move |document: &Document| {
+        document.content.buff.set_text(&text);
+        document.content.buff.place_cursor(&document.content.buff.get_start_iter());
+        tabs_clone.present(document);
+    };
+
+    match snapshot.path {
+        Some(ref path) if check_file(path).is_ok() => {
+            let _ = load_file(tabs, window, path.clone(), fill);
+        }
+        _ => fill(&tabs.target()),
+    }
+}
+
+/// Writes down every tab of `tabs`, in order, for the next launch to restore.
+/// Unsaved documents from an earlier session that was not restored are kept
+/// too, so quitting never loses them. A restored session is forgotten as it
+/// is restored, so whatever is still saved was not.
+pub fn save_session(tabs: &Tabs) -> io::Result<()> {
+    let mut documents: Vec<Document> = tabs
+        .documents()
+        .into_iter()
+        .filter(|document| !document.is_blank() && !document.is_binary() && !document.is_loading())
+        .collect();
+    documents.sort_by_key(|document| tabs.notebook.page_num(&document.page));
+
+    let active = documents.iter().position(|document| tabs.is_active(document)).unwrap_or(0);
+    let mut states: Vec<DocumentState> = documents.iter().map(document_state).collect();
+    if let Some(earlier) = session::load() {
+        for state in earlier.documents.into_iter().filter(|state| state.unsaved.is_some()) {
+            // A file open now is saved as it is now, and the same unsaved
+            // text is only kept once.
+            let is_open = state.path.is_some() && states.iter().any(|kept| kept.path == state.path);
+            let is_kept = states
+                .iter()
+                .any(|kept| kept.path == state.path && kept.unsaved == state.unsaved);
+            if !is_open && !is_kept {
+                states.push(state);
+            }
+        }
+    }
 
-        if new_file.writable() {
-            path_label.set_text(&new_file.to_string_lossy());
+    session::save(&Session {
+        documents: states,
+        active,
+    })
+}
+
+fn document_state(document: &Document) -> DocumentState {
+    let buff = &document.content.buff;
+    let view = &document.content.view;
+    let offset = |mark: Option<TextMark>| mark.map_or(0, |mark| buff.get_iter_at_mark(&mark).get_offset());
+    let visible = view.get_visible_rect();
+    let scroll = view
+        .get_iter_at_location(visible.x, visible.y)
+        .map_or(0, |mut iter| {
+            iter.set_line_offset(0);
+            iter.get_offset()
+        });
+
+    let lock = document.current_file.read().unwrap();
+    DocumentState {
+        path: lock.as_ref().map(|file| file.get_path().to_path_buf()),
+        unsaved: if document.is_modified() || lock.is_none() {
+            get_buffer(buff).map(|text| text.to_string())
         } else {
-            path_label.set_text(&format!("{} - [READ ONLY]", &new_file.to_string_lossy()));
+            None
+        },
+        cursor: offset(buff.get_insert()),
+        selection_bound: offset(buff.get_selection_bound()),
+        scroll,
+        language: document.language_id(),
+        encoding: lock.as_ref().map(|file| file.get_encoding().id()),
+    }
+}
+
+/// Reopens the tabs of the last session, with their unsaved changes, and
+/// forgets it. Files that are gone are left out unless they had changes,
+/// which then come back in a new tab.
+pub fn restore_session(tabs: &Tabs, window: &Window) {
+    let saved = match session::load() {
+        Some(saved) => saved,
+        None => return,
+    };
+    session::remove();
+
+    for (index, state) in saved.documents.into_iter().enumerate() {
+        let path = state.path.clone();
+        let has_unsaved = state.unsaved.is_some();
+        let active = index == saved.active;
+        let tabs_clone = tabs.clone();
+        let fill = move |document: &Document| {
+            apply_state(&tabs_clone, document, &state);
+            if active {
+                tabs_clone.present(document);
+            }
+        };
+
+        match path {
+            Some(path) if check_file(&path).is_ok() => {
+                let _ = load_file(tabs, window, path, fill);
+            }
+            _ if has_unsaved => fill(&tabs.target()),
+            _ => (),
+        }
+    }
+    tabs.refresh();
+}
+
+/// Puts the text, highlighting, cursor and scroll position of `state` back
+/// into `document`.
+fn apply_state(tabs: &Tabs, document: &Document, state: &DocumentState) {
+    let buff = &document.content.buff;
+    if let Some(encoding) = state.encoding.as_ref().and_then(|id| TextEncoding::from_id(id)) {
+        change_encoding(tabs, document, encoding);
+    }
+    if let Some(ref text) = state.unsaved {
+        buff.set_text(text);
+    }
+    if let Some(ref language) = state.language {
+        document.choose_language(language);
+    }
+
+    buff.select_range(
+        &buff.get_iter_at_offset(state.cursor),
+        &buff.get_iter_at_offset(state.selection_bound),
+    );
+    // The view scrolls once it has laid the text out, so the mark has to
+    // stay where it is until then; it goes when the main loop is idle again.
+    let top = TextMark::new(None, true);
+    buff.add_mark(&top, &buff.get_iter_at_offset(state.scroll));
+    document.content.view.scroll_to_mark(&top, 0.0, true, 0.0, 0.0);
+    let buff = buff.clone();
+    glib::idle_add_local(move || {
+        if !top.get_deleted() {
+            buff.delete_mark(&top);
         }
-        if let Some(filename) = new_file.file_name() {
-            headerbar.set_subtitle(Some(&filename.to_string_lossy()));
+        glib::Continue(false)
+    });
+}
+
+/// Decodes the file `path` holding `data` into what the document needs: the
+/// text with LF line breaks, and the file state it is saved back with.
+fn prepare(path: PathBuf, data: &[u8], contents: String, encoding: TextEncoding) -> Loaded {
+    let (line_ending, mixed) = line_ending::detect(&contents);
+    let contents = LineEnding::Lf.apply(&contents);
+
+    let mut file = ActiveMetadata::new(path, &contents.as_bytes(), encoding, line_ending);
+    if mixed {
+        file.set_mixed_line_endings();
+    }
+    file.set_disk_state(data);
+    Loaded { file, contents, mixed }
+}
+
+/// Fills `document` with `contents`, decoded from the bytes `data` read from
+/// `path`, and watches the file from then on. The buffer always holds LF
+/// line breaks; the style found in the file is restored on save.
+fn set_contents(tabs: &Tabs, document: &Document, path: PathBuf, data: &[u8], contents: String, encoding: TextEncoding) {
+    let loaded = prepare(path, data, contents, encoding);
+    *document.current_file.write().unwrap() = Some(loaded.file);
+    document.content.buff.set_text(&loaded.contents);
+    finish_load(tabs, document, loaded.mixed);
+}
+
+/// Settles `document` once its file and text are in place.
+fn finish_load(tabs: &Tabs, document: &Document, mixed: bool) {
+    document.content.buff.place_cursor(&document.content.buff.get_start_iter());
+    document.set_clean();
+    tabs.watch(document);
+    document.guess_language();
+    if let Some(path) = document.path() {
+        recent::remember(&path);
+    }
+
+    let line_ending = match *document.current_file.read().unwrap() {
+        Some(ref file) => file.get_line_ending(),
+        None => LineEnding::Lf,
+    };
+    if mixed {
+        document.show_message(
+            MessageType::Warning,
+            &format!(
+                "This file mixes different line endings. Saving it will convert them all to {}, \
+                 or pick another style in the status bar.",
+                line_ending.label()
+            ),
+        );
+    } else if document.is_large() {
+        document.show_message(
+            MessageType::Info,
+            "This file is large, so syntax highlighting and line wrapping are turned off, \
+             and any edit marks it as modified.",
+        );
+    } else {
+        document.hide_message();
+    }
+    tabs.refresh();
+}
+
+/// Reopens the file behind `document` in `encoding`. When the document has
+/// unsaved changes, or the bytes on disk are not valid in `encoding`, the
+/// text is kept and `encoding` is used the next time it is saved instead.
+pub fn change_encoding(tabs: &Tabs, document: &Document, encoding: TextEncoding) {
+    let path = match *document.current_file.read().unwrap() {
+        Some(ref file) if file.get_encoding() != encoding => file.get_path().to_path_buf(),
+        _ => return,
+    };
+
+    let reopened = if document.is_modified() {
+        None
+    } else {
+        fs::read(&path)
+            .ok()
+            .and_then(|data| encoding.decode(&data).map(|contents| (data, contents)))
+    };
+
+    match reopened {
+        Some((data, contents)) => set_contents(tabs, document, path, &data, contents, encoding),
+        None => {
+            if let Some(ref mut file) = *document.current_file.write().unwrap() {
+                file.set_encoding(encoding);
+            }
+            document.update_modified();
+            tabs.refresh();
         }
+    }
+}
 
-        *current_file.write().unwrap() = Some(ActiveMetadata::new(new_file, &contents.as_bytes()));
-        editor.set_text(&contents);
-        editor.place_cursor(&editor.get_start_iter());
+/// Converts every line break in `document` to `line_ending` on the next save.
+pub fn change_line_ending(tabs: &Tabs, document: &Document, line_ending: LineEnding) {
+    // Only take the write lock for an actual change; the combo also reports
+    // the values `Tabs::refresh` copies into it.
+    let unchanged = match *document.current_file.read().unwrap() {
+        Some(ref file) => file.get_line_ending() == line_ending,
+        None => true,
+    };
+    if unchanged {
+        return;
+    }
+    if let Some(ref mut file) = *document.current_file.write().unwrap() {
+        file.set_line_ending(line_ending);
     }
+
+    document.hide_message();
+    document.update_modified();
+    tabs.refresh();
 }
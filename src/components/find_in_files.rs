@@ -0,0 +1,470 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+use super::misc::get_buffer;
+use super::{ErrorDialog, Tabs};
+use crate::search::{self, FileHits, Query, Replacement};
+use glib::{ToValue, Type};
+use gtk::prelude::TreeStoreExtManual;
+use gtk::*;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+/// The search entry's tooltip. Folders are searched with Rust's regex
+/// engine rather than the search bar's PCRE, and the two part ways on a few
+/// constructs.
+const SEARCH_TOOLTIP: &str = "Press Enter to search\n\
+    Regular expressions here cannot use look-around or backreferences such as \\1, \
+    which the search bar supports";
+
+/// Longest stretch of a line shown as a preview, in characters.
+const PREVIEW_LEN: usize = 200;
+
+// Columns of the results store. File rows have `IS_FILE` set and carry the
+// opt-out toggle; their children are the matching lines.
+const INCLUDE: u32 = 0;
+const MARKUP: u32 = 1;
+const PATH: u32 = 2;
+const LINE: u32 = 3;
+const COLUMN: u32 = 4;
+const IS_FILE: u32 = 5;
+const TEXT: u32 = 6;
+
+enum SearchMessage {
+    Found(FileHits),
+    Done,
+}
+
+/// The side panel that searches, and replaces, across a whole folder.
+#[derive(Clone)]
+pub struct FindInFiles {
+    pub container: Box,
+    pub folder: FileChooserButton,
+    pub search_entry: SearchEntry,
+    pub replace_entry: Entry,
+    pub replace_button: Button,
+    pub results: TreeView,
+    store: TreeStore,
+    status: Label,
+    canceled: Arc<RwLock<Option<Arc<AtomicBool>>>>,
+    regex: Arc<RwLock<Option<(Regex, bool)>>>,
+}
+
+impl FindInFiles {
+    pub fn new() -> FindInFiles {
+        let container = Box::new(Orientation::Vertical, 6);
+        container.set_border_width(6);
+        container.set_size_request(320, -1);
+        // Hidden until asked for, even when the window is shown.
+        container.set_no_show_all(true);
+
+        let folder = FileChooserButton::new("Select a Folder", FileChooserAction::SelectFolder);
+        let search_entry = SearchEntry::new();
+        search_entry.set_placeholder_text(Some("Find in Files"));
+        search_entry.set_tooltip_text(Some(SEARCH_TOOLTIP));
+
+        let replace_grid = Grid::new();
+        replace_grid
+            .get_style_context()
+            .add_class(&STYLE_CLASS_LINKED);
+        let replace_entry = Entry::new();
+        replace_entry.set_hexpand(true);
+        replace_entry.set_placeholder_text(Some("Replace With"));
+        let replace_button = Button::new_with_label("Replace");
+        replace_button.set_tooltip_text(Some("Replace in the checked files"));
+        replace_button.set_sensitive(false);
+        replace_grid.add(&replace_entry);
+        replace_grid.add(&replace_button);
+
+        let status = Label::new(None);
+        status.set_xalign(0.0);
+        status.set_ellipsize(pango::EllipsizeMode::End);
+        status.get_style_context().add_class(&STYLE_CLASS_DIM_LABEL);
+
+        let store = TreeStore::new(&[
+            Type::Bool,
+            Type::String,
+            Type::String,
+            Type::U32,
+            Type::U32,
+            Type::Bool,
+            Type::String,
+        ]);
+        let results = TreeView::new_with_model(&store);
+        results.set_headers_visible(false);
+        results.set_tooltip_column(PATH as i32);
+
+        let column = TreeViewColumn::new();
+        let include = CellRendererToggle::new();
+        column.pack_start(&include, false);
+        column.add_attribute(&include, "active", INCLUDE as i32);
+        column.add_attribute(&include, "visible", IS_FILE as i32);
+        let text = CellRendererText::new();
+        text.set_property_ellipsize(pango::EllipsizeMode::End);
+        column.pack_start(&text, true);
+        column.add_attribute(&text, "markup", MARKUP as i32);
+        results.append_column(&column);
+
+        let store_clone = store.clone();
+        include.connect_toggled(move |_, path| {
+            if let Some(iter) = store_clone.get_iter(&path) {
+                let active = store_clone.get_value(&iter, INCLUDE as i32).get_some::<bool>().unwrap_or(false);
+                store_clone.set_value(&iter, INCLUDE, &(!active).to_value());
+            }
+        });
+
+        let scroller = ScrolledWindow::new(NONE_ADJUSTMENT, NONE_ADJUSTMENT);
+        scroller.set_vexpand(true);
+        scroller.add(&results);
+
+        container.pack_start(&folder, false, false, 0);
+        container.pack_start(&search_entry, false, false, 0);
+        container.pack_start(&replace_grid, false, false, 0);
+        container.pack_start(&status, false, false, 0);
+        container.pack_start(&scroller, true, true, 0);
+        for child in container.get_children() {
+            child.show_all();
+        }
+
+        FindInFiles {
+            container,
+            folder,
+            search_entry,
+            replace_entry,
+            replace_button,
+            results,
+            store,
+            status,
+            canceled: Arc::new(RwLock::new(None)),
+            regex: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Starts searching the chosen folder in the background, replacing the
+    /// results of any earlier search. Files are listed as they are found.
+    pub fn search(&self, query: Query) {
+        self.cancel();
+        self.store.clear();
+        self.replace_button.set_sensitive(false);
+        self.search_entry.get_style_context().remove_class(&STYLE_CLASS_ERROR);
+        self.search_entry.set_tooltip_text(Some(SEARCH_TOOLTIP));
+
+        let root = match self.folder.get_filename() {
+            Some(root) => root,
+            None => {
+                self.status.set_text("Choose a folder to search");
+                return;
+            }
+        };
+        if query.text.is_empty() {
+            self.status.set_text("");
+            return;
+        }
+        let regex = match query.compile() {
+            Ok(regex) => regex,
+            Err(error) => {
+                self.search_entry.get_style_context().add_class(&STYLE_CLASS_ERROR);
+                self.search_entry.set_tooltip_text(Some(&error.to_string()));
+                self.status.set_text("The regular expression is not valid");
+                return;
+            }
+        };
+        *self.regex.write().unwrap() = Some((regex.clone(), !query.regex));
+        self.status.set_text("Searching…");
+
+        let canceled = Arc::new(AtomicBool::new(false));
+        *self.canceled.write().unwrap() = Some(canceled.clone());
+
+        let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        let canceled_clone = canceled.clone();
+        let root_clone = root.clone();
+        thread::spawn(move || {
+            search::search_tree(&root_clone, &regex, &canceled_clone, &mut |hits| {
+                let _ = sender.send(SearchMessage::Found(hits));
+            });
+            let _ = sender.send(SearchMessage::Done);
+        });
+
+        let panel = self.clone();
+        let (mut files, mut lines) = (0, 0);
+        receiver.attach(None, move |message| {
+            if canceled.load(Ordering::SeqCst) {
+                return glib::Continue(false);
+            }
+            match message {
+                SearchMessage::Found(hits) => {
+                    files += 1;
+                    lines += hits.hits.len();
+                    panel.add_file(&root, &hits);
+                    panel.status.set_text(&format!("Searching… {}", summary(lines, files)));
+                    glib::Continue(true)
+                }
+                SearchMessage::Done => {
+                    if files == 0 {
+                        panel.status.set_text("No matches");
+                    } else {
+                        panel.status.set_text(&summary(lines, files));
+                    }
+                    panel.replace_button.set_sensitive(files > 0);
+                    glib::Continue(false)
+                }
+            }
+        });
+    }
+
+    /// Stops the search in progress, if any.
+    pub fn cancel(&self) {
+        if let Some(canceled) = self.canceled.write().unwrap().take() {
+            canceled.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn add_file(&self, root: &Path, hits: &FileHits) {
+        let relative = hits.path.strip_prefix(root).unwrap_or(&hits.path);
+        let markup = format!(
+            "<b>{}</b> ({})",
+            glib::markup_escape_text(&relative.to_string_lossy()),
+            hits.hits.len()
+        );
+        let path = hits.path.to_string_lossy().into_owned();
+        let file = self.store.insert_with_values(
+            None,
+            None,
+            &[INCLUDE, MARKUP, PATH, LINE, COLUMN, IS_FILE, TEXT],
+            &[&true, &markup, &path, &0u32, &0u32, &true, &""],
+        );
+
+        let replacement = self.preview_replacement();
+        for hit in &hits.hits {
+            let markup = self.preview(hit.line, &hit.text, replacement.as_ref());
+            self.store.insert_with_values(
+                Some(&file),
+                None,
+                &[INCLUDE, MARKUP, PATH, LINE, COLUMN, IS_FILE, TEXT],
+                &[&true, &markup, &path, &hit.line, &hit.column, &false, &hit.text],
+            );
+        }
+        if let Some(path) = self.store.get_path(&file) {
+            self.results.expand_row(&path, false);
+        }
+    }
+
+    /// Redraws the line previews, showing what the replace entry would turn
+    /// each match into.
+    pub fn refresh_previews(&self) {
+        let replacement = self.preview_replacement();
+        let mut file = self.store.get_iter_first();
+        while let Some(file_iter) = file {
+            if let Some(line) = self.store.iter_children(Some(&file_iter)) {
+                loop {
+                    let number = self.store.get_value(&line, LINE as i32).get_some::<u32>().unwrap_or(0);
+                    let text = self.store.get_value(&line, TEXT as i32).get::<String>().ok().flatten();
+                    let markup = self.preview(number, &text.unwrap_or_default(), replacement.as_ref());
+                    self.store.set_value(&line, MARKUP, &markup.to_value());
+                    if !self.store.iter_next(&line) {
+                        break;
+                    }
+                }
+            }
+            file = if self.store.iter_next(&file_iter) {
+                Some(file_iter)
+            } else {
+                None
+            };
+        }
+    }
+
+    /// What the replace entry turns matches into, read like the search bar
+    /// reads it. Without the regex option the text is used as it is.
+    fn replacement(&self) -> Result<Replacement, String> {
+        let text = self.replace_entry.get_text().map(|text| text.to_string()).unwrap_or_default();
+        match *self.regex.read().unwrap() {
+            Some((ref regex, false)) => Replacement::parse(&text, regex),
+            _ => Ok(Replacement::literal(&text)),
+        }
+    }
+
+    /// The replacement, after flagging the replace entry when it refers to
+    /// groups the search does not have or to escapes that do not exist.
+    fn checked_replacement(&self) -> Option<Replacement> {
+        let replacement = self.replacement();
+        match replacement {
+            Ok(_) => {
+                self.replace_entry.get_style_context().remove_class(&STYLE_CLASS_ERROR);
+                self.replace_entry.set_tooltip_text(None);
+            }
+            Err(ref error) => {
+                self.replace_entry.get_style_context().add_class(&STYLE_CLASS_ERROR);
+                self.replace_entry.set_tooltip_text(Some(error));
+            }
+        }
+        replacement.ok()
+    }
+
+    /// The replacement to show in the previews, if one was typed.
+    fn preview_replacement(&self) -> Option<Replacement> {
+        self.checked_replacement().filter(|_| self.replace_entry.get_text_length() > 0)
+    }
+
+    /// The markup for one matching line: the matches in bold, or struck out
+    /// and followed by their replacement.
+    fn preview(&self, line: u32, text: &str, replacement: Option<&Replacement>) -> String {
+        let regex = self.regex.read().unwrap();
+        let regex = match *regex {
+            Some((ref regex, _)) => regex,
+            None => return String::new(),
+        };
+
+        let text = text.trim_start();
+        let text = match text.char_indices().nth(PREVIEW_LEN) {
+            Some((end, _)) => &text[..end],
+            None => text,
+        };
+        let mut markup = format!("<span alpha=\"60%\">{}:</span> ", line);
+        let mut last = 0;
+        for captures in regex.captures_iter(text) {
+            let found = captures.get(0).unwrap();
+            markup.push_str(&glib::markup_escape_text(&text[last..found.start()]));
+            match replacement {
+                Some(replacement) => {
+                    let mut new = String::new();
+                    replacement.expand(&captures, &mut new);
+                    markup.push_str(&format!(
+                        "<s>{}</s><b>{}</b>",
+                        glib::markup_escape_text(found.as_str()),
+                        glib::markup_escape_text(&new)
+                    ));
+                }
+                None => markup.push_str(&format!("<b>{}</b>", glib::markup_escape_text(found.as_str()))),
+            }
+            last = found.end();
+        }
+        markup.push_str(&glib::markup_escape_text(&text[last..]));
+        markup
+    }
+
+    /// The file and position of the matching line at `path`, counted from 0.
+    /// `None` for file rows.
+    pub fn hit_at(&self, path: &TreePath) -> Option<(PathBuf, i32, i32)> {
+        let iter = self.store.get_iter(path)?;
+        if self.store.get_value(&iter, IS_FILE as i32).get_some::<bool>().unwrap_or(true) {
+            return None;
+        }
+        let file = self.store.get_value(&iter, PATH as i32).get::<String>().ok()??;
+        let line = self.store.get_value(&iter, LINE as i32).get_some::<u32>().ok()?;
+        let column = self.store.get_value(&iter, COLUMN as i32).get_some::<u32>().ok()?;
+        Some((PathBuf::from(file), line as i32 - 1, column as i32))
+    }
+
+    /// Replaces the matches in every checked file. Files open in a tab are
+    /// changed in the editor, match by match so that the cursor and marks
+    /// stay put, as one undoable step, and left unsaved; the others are
+    /// rewritten on disk.
+    pub fn replace(&self, tabs: &Tabs, window: &Window) {
+        let regex = match *self.regex.read().unwrap() {
+            Some((ref regex, _)) => regex.clone(),
+            None => return,
+        };
+        let replacement = match self.checked_replacement() {
+            Some(replacement) => replacement,
+            None => {
+                self.status.set_text("The replacement is not valid");
+                return;
+            }
+        };
+
+        let mut paths = Vec::new();
+        let mut file = self.store.get_iter_first();
+        while let Some(file_iter) = file {
+            if self.store.get_value(&file_iter, INCLUDE as i32).get_some::<bool>().unwrap_or(false) {
+                if let Ok(Some(path)) = self.store.get_value(&file_iter, PATH as i32).get::<String>() {
+                    paths.push(PathBuf::from(path));
+                }
+            }
+            file = if self.store.iter_next(&file_iter) {
+                Some(file_iter)
+            } else {
+                None
+            };
+        }
+
+        let (mut replaced, mut files) = (0, 0);
+        let mut failures = Vec::new();
+        let mut overwritten = Vec::new();
+        for path in paths {
+            let result = match tabs.find(&path) {
+                Some(ref document) if !document.is_loading() => {
+                    let buff = &document.content.buff;
+                    let text = get_buffer(buff).map(|text| text.to_string()).unwrap_or_default();
+                    let replacements = search::replacements(&regex, &text, &replacement);
+                    if !replacements.is_empty() {
+                        buff.begin_user_action();
+                        // From the last match back, so the offsets of the
+                        // others still hold.
+                        for &(start, end, ref new) in replacements.iter().rev() {
+                            let mut start = buff.get_iter_at_offset(start);
+                            let mut end = buff.get_iter_at_offset(end);
+                            buff.delete(&mut start, &mut end);
+                            buff.insert(&mut start, new);
+                        }
+                        buff.end_user_action();
+                    }
+                    Ok((replacements.len(), None))
+                }
+                _ => search::replace_in_file(&path, &regex, &replacement),
+            };
+            match result {
+                Ok((0, _)) => (),
+                Ok((count, fallback)) => {
+                    replaced += count;
+                    files += 1;
+                    if let Some(fallback) = fallback {
+                        overwritten.push(fallback.describe(&path));
+                    }
+                }
+                Err(error) => failures.push(format!("{}: {}", path.to_string_lossy(), error)),
+            }
+        }
+
+        self.store.clear();
+        self.replace_button.set_sensitive(false);
+        self.status.set_text(&format!("Replaced {}", summary(replaced, files)));
+        if !failures.is_empty() {
+            let error_dialog = ErrorDialog::new(window, "Some files could not be changed", &failures.join("\n"));
+            let _ = error_dialog.run();
+        }
+        if !overwritten.is_empty() {
+            let error_dialog = ErrorDialog::new(window, "Some files were not saved safely", &overwritten.join("\n"));
+            let _ = error_dialog.run();
+        }
+    }
+}
+
+fn summary(matches: usize, files: usize) -> String {
+    format!(
+        "{} {} in {} {}",
+        matches,
+        if matches == 1 { "match" } else { "matches" },
+        files,
+        if files == 1 { "file" } else { "files" }
+    )
+}
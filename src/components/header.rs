@@ -17,19 +17,30 @@
  * Authored by: mazen <https://github.com/maze-n>
  */
 
+use super::RecentFiles;
 use gio::SettingsExt;
 use gtk::*;
 
 pub struct Header {
     pub container: HeaderBar,
+    pub new_tab: Button,
     pub open: Button,
+    pub recent: RecentFiles,
     pub save: Button,
     pub save_as: Button,
     pub theme_switch: Switch,
     pub font_button: FontButton,
     pub find_button: ToggleButton,
+    pub find_in_files_button: ToggleButton,
+    pub go_to_button: MenuButton,
+    pub go_to_entry: Entry,
+    pub go_to_message: Label,
     pub enable_wrapping_word: CheckButton,
     pub enable_wrapping_char: CheckButton,
+    pub enable_autosave: CheckButton,
+    pub autosave_interval: SpinButton,
+    pub restore_session: CheckButton,
+    pub large_file_threshold: SpinButton,
 }
 
 impl Header {
@@ -41,9 +52,13 @@ impl Header {
 
         let settings = gio::Settings::new("com.github.maze-n.eddit");
 
+        let new_tab = Button::new_from_icon_name(Some("document-new"), IconSize::LargeToolbar);
+        new_tab.set_tooltip_text(Some("New file\n Ctrl + N"));
+        new_tab.set_valign(Align::Center);
         let open = Button::new_from_icon_name(Some("document-open"), IconSize::LargeToolbar);
         open.set_tooltip_text(Some("Open a file\n   Ctrl + O"));
         open.set_valign(Align::Center);
+        let recent = RecentFiles::new();
         let save = Button::new_from_icon_name(Some("document-save"), IconSize::LargeToolbar);
         save.set_tooltip_text(Some("Save file\n Ctrl + S"));
         save.set_valign(Align::Center);
@@ -58,6 +73,29 @@ impl Header {
         find_button.set_image(Some(&Image::new_from_icon_name(Some("edit-find"), IconSize::LargeToolbar)));
         find_button.set_valign(Align::Center);
         find_button.set_tooltip_text(Some("Find and replace\n         Ctrl + F"));
+        let find_in_files_button = ToggleButton::new();
+        find_in_files_button.set_image(Some(&Image::new_from_icon_name(Some("system-search"), IconSize::LargeToolbar)));
+        find_in_files_button.set_valign(Align::Center);
+        find_in_files_button.set_tooltip_text(Some("Find in files\n Ctrl + Shift + F"));
+
+        let go_to_button = MenuButton::new();
+        go_to_button.set_image(Some(&Image::new_from_icon_name(Some("go-jump"), IconSize::LargeToolbar)));
+        go_to_button.set_valign(Align::Center);
+        go_to_button.set_tooltip_text(Some("Go to line\n Ctrl + L"));
+        let go_to_entry = Entry::new();
+        go_to_entry.set_width_chars(24);
+        go_to_entry.set_tooltip_text(Some("A line, line:column, +lines, -lines or a percentage"));
+        let go_to_message = Label::new(None);
+        go_to_message.set_halign(Align::Start);
+        go_to_message.get_style_context().add_class(&STYLE_CLASS_DIM_LABEL);
+        let go_to_container = Box::new(Orientation::Vertical, 6);
+        go_to_container.set_border_width(12);
+        go_to_container.pack_start(&go_to_entry, false, false, 0);
+        go_to_container.pack_start(&go_to_message, false, false, 0);
+        go_to_container.show_all();
+        let go_to_popover = Popover::new(Some(&go_to_button));
+        go_to_popover.add(&go_to_container);
+        go_to_button.set_popover(Some(&go_to_popover));
 
         let popover = Popover::new(Some(&menu_button));
         let pop_container = Box::new(Orientation::Vertical, 6);
@@ -110,6 +148,43 @@ impl Header {
             revealer_clone.set_reveal_child(enable_wrapping_word.get_active());
         });
 
+        let autosave_header_label = Label::new(Some("Autosave"));
+        autosave_header_label.set_halign(Align::Start);
+
+        let enable_autosave = CheckButton::new_with_label("Save files automatically");
+        enable_autosave.set_active(settings.get_boolean("autosave"));
+
+        let autosave_interval = SpinButton::new_with_range(1.0, 3600.0, 1.0);
+        autosave_interval.set_value(settings.get_int("autosave-interval") as f64);
+        let autosave_interval_box = Box::new(Orientation::Horizontal, 6);
+        autosave_interval_box.pack_start(&Label::new(Some("After")), false, false, 0);
+        autosave_interval_box.pack_start(&autosave_interval, false, false, 0);
+        autosave_interval_box.pack_start(&Label::new(Some("seconds idle")), false, false, 0);
+        autosave_interval_box.set_sensitive(enable_autosave.get_active());
+
+        let autosave_interval_box_clone = autosave_interval_box.clone();
+        enable_autosave.connect_toggled(move |enable_autosave| {
+            autosave_interval_box_clone.set_sensitive(enable_autosave.get_active());
+        });
+
+        let session_header_label = Label::new(Some("Session"));
+        session_header_label.set_halign(Align::Start);
+
+        let restore_session = CheckButton::new_with_label("Reopen documents from last time");
+        restore_session.set_active(settings.get_boolean("restore-session"));
+        restore_session.set_tooltip_text(Some("Documents with unsaved changes are kept without asking"));
+
+        let large_file_header_label = Label::new(Some("Large Files"));
+        large_file_header_label.set_halign(Align::Start);
+
+        let large_file_threshold = SpinButton::new_with_range(1.0, 1024.0, 1.0);
+        large_file_threshold.set_value(settings.get_int("large-file-threshold") as f64);
+        large_file_threshold.set_tooltip_text(Some("Bigger files open without highlighting or wrapping"));
+        let large_file_threshold_box = Box::new(Orientation::Horizontal, 6);
+        large_file_threshold_box.pack_start(&Label::new(Some("Above")), false, false, 0);
+        large_file_threshold_box.pack_start(&large_file_threshold, false, false, 0);
+        large_file_threshold_box.pack_start(&Label::new(Some("MB")), false, false, 0);
+
         pop_container.pack_start(&theme_selector, true, true, 0);
         pop_container.pack_start(&Separator::new(Orientation::Horizontal), true, true, 6);
         pop_container.pack_start(&font_button, true, true, 0);
@@ -117,28 +192,52 @@ impl Header {
         pop_container.pack_start(&text_wrap_header_label, true, true, 0);
         pop_container.pack_start(&enable_wrapping_word, true, true, 0);
         pop_container.pack_start(&revealer, true, true, 0);
+        pop_container.pack_start(&Separator::new(Orientation::Horizontal), true, true, 0);
+        pop_container.pack_start(&autosave_header_label, true, true, 0);
+        pop_container.pack_start(&enable_autosave, true, true, 0);
+        pop_container.pack_start(&autosave_interval_box, true, true, 0);
+        pop_container.pack_start(&Separator::new(Orientation::Horizontal), true, true, 0);
+        pop_container.pack_start(&session_header_label, true, true, 0);
+        pop_container.pack_start(&restore_session, true, true, 0);
+        pop_container.pack_start(&Separator::new(Orientation::Horizontal), true, true, 0);
+        pop_container.pack_start(&large_file_header_label, true, true, 0);
+        pop_container.pack_start(&large_file_threshold_box, true, true, 0);
         pop_container.show_all();
 
         popover.add(&pop_container);
 
         menu_button.set_popover(Some(&popover));
 
+        container.pack_start(&new_tab);
         container.pack_start(&open);
+        container.pack_start(&recent.button);
         container.pack_start(&save);
         container.pack_start(&save_as);
         container.pack_end(&menu_button);
         container.pack_end(&find_button);
+        container.pack_end(&find_in_files_button);
+        container.pack_end(&go_to_button);
 
         Header {
             container,
+            new_tab,
             open,
+            recent,
             save,
             save_as,
             theme_switch,
             font_button,
             find_button,
+            find_in_files_button,
+            go_to_button,
+            go_to_entry,
+            go_to_message,
             enable_wrapping_word,
             enable_wrapping_char,
+            enable_autosave,
+            autosave_interval,
+            restore_session,
+            large_file_threshold,
         }
     }
 }
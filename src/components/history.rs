@@ -0,0 +1,91 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+use gio::SettingsExt;
+use glib::{IsA, Type};
+use gtk::prelude::GtkListStoreExtManual;
+use gtk::*;
+
+/// How many strings a history keeps.
+const MAX_HISTORY: usize = 25;
+
+/// Recently used strings, newest first and without duplicates, kept in a
+/// gsettings key and offered as completions in the entries attached to it.
+#[derive(Clone)]
+pub struct History {
+    key: &'static str,
+    store: ListStore,
+    settings: gio::Settings,
+}
+
+impl History {
+    pub fn new(key: &'static str) -> History {
+        let history = History {
+            key,
+            store: ListStore::new(&[Type::String]),
+            settings: gio::Settings::new("com.github.maze-n.eddit"),
+        };
+        history.fill(&history.items());
+        history
+    }
+
+    /// Completes what is typed into `entry` from this history.
+    pub fn attach<E: IsA<Entry>>(&self, entry: &E) {
+        let completion = EntryCompletion::new();
+        completion.set_model(Some(&self.store));
+        completion.set_text_column(0);
+        completion.set_minimum_key_length(1);
+        completion.set_popup_single_match(false);
+        entry.set_completion(Some(&completion));
+    }
+
+    /// Moves `text` to the front of the history.
+    pub fn add(&self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+
+        let mut items = self.items();
+        if items.first().map_or(false, |first| first == text) {
+            return;
+        }
+        items.retain(|item| item != text);
+        items.insert(0, text.to_string());
+        items.truncate(MAX_HISTORY);
+
+        let values: Vec<&str> = items.iter().map(String::as_str).collect();
+        let _ = self.settings.set_strv(self.key, &values);
+        self.fill(&items);
+    }
+
+    fn items(&self) -> Vec<String> {
+        self.settings
+            .get_strv(self.key)
+            .into_iter()
+            .map(|item| item.to_string())
+            .collect()
+    }
+
+    fn fill(&self, items: &[String]) {
+        self.store.clear();
+        for item in items {
+            self.store.insert_with_values(None, &[0], &[item]);
+        }
+    }
+}
@@ -17,6 +17,7 @@
  * Authored by: mazen <https://github.com/maze-n>
  */
 
+use super::Document;
 use gio::SettingsExt;
 use glib::GString;
 use gtk::*;
@@ -44,30 +45,37 @@ pub fn before_quit(window: &Window, word_wrap_checkbox: &CheckButton, char_wrap_
     settings.set_boolean("text-wrap-char", char_wrap);
 }
 
-pub fn set_sensitivity (entry: &SearchEntry, up: &Button, down: &Button, text: &str, iter: &TextIter) {
-    let search_flag = TextSearchFlags::CASE_INSENSITIVE;
-    if text != "" {
-        if let Some(_) = iter.forward_search(text, search_flag, None) {
-            down.set_sensitive(true);
-        } else {
-            down.set_sensitive(false);
-        }
-    
-        if let Some(_) = iter.backward_search(text, search_flag, None) {
-            up.set_sensitive(true);
-        } else {
-            up.set_sensitive(false);
-        }
-    } else {
-        up.set_sensitive(false);
-        down.set_sensitive(false);
-    }
+/// Enables the previous and next buttons when `document` has a match before
+/// or after `iter`, and flags the entry when nothing matches or the regular
+/// expression does not compile.
+pub fn set_sensitivity(entry: &SearchEntry, up: &Button, down: &Button, document: &Document, iter: &TextIter) {
+    let context = &document.content.search_context;
+    let searching = context
+        .get_settings()
+        .and_then(|settings| settings.get_search_text())
+        .map_or(false, |text| !text.is_empty());
+    let regex_error = context.get_regex_error();
+
+    up.set_sensitive(searching && regex_error.is_none() && document.previous_match(iter).is_some());
+    down.set_sensitive(searching && regex_error.is_none() && document.next_match(iter).is_some());
 
-    if !up.get_sensitive() && !down.get_sensitive() && text != "" {
+    if searching && !up.get_sensitive() && !down.get_sensitive() {
         entry.get_style_context().add_class(&gtk::STYLE_CLASS_ERROR);
         entry.set_icon_from_icon_name(EntryIconPosition::Primary, Some("dialog-error-symbolic"));
     } else {
         entry.get_style_context().remove_class(&gtk::STYLE_CLASS_ERROR);
         entry.set_icon_from_icon_name(EntryIconPosition::Primary, Some("edit-find-symbolic"));
     }
-}
\ No newline at end of file
+    entry.set_tooltip_text(regex_error.map(|error| error.to_string()).as_ref().map(String::as_str));
+}
+
+/// Flags the replace entry when the replacement text was rejected, with the
+/// reason as its tooltip. `None` clears the flag.
+pub fn set_replace_error(entry: &Entry, error: Option<&str>) {
+    if error.is_some() {
+        entry.get_style_context().add_class(&gtk::STYLE_CLASS_ERROR);
+    } else {
+        entry.get_style_context().remove_class(&gtk::STYLE_CLASS_ERROR);
+    }
+    entry.set_tooltip_text(error);
+}
@@ -20,13 +20,25 @@
 mod app;
 mod content;
 mod dialog;
+mod find_in_files;
 pub mod file_operations;
 mod header;
+mod history;
 pub mod misc;
+mod recent;
 mod searchbox;
+mod statusbar;
+mod tabs;
+mod windows;
 
-pub use self::app::App;
+pub use self::app::{App, ConnectedApp};
 pub use self::content::Content;
-pub use self::dialog::{OpenDialog, SaveDialog, UnsavedDialog, ErrorDialog};
+pub use self::dialog::{OpenDialog, SaveDialog, UnsavedDialog, ErrorDialog, RecoveryDialog, CompareDialog};
+pub use self::find_in_files::FindInFiles;
 pub use self::header::Header;
+pub use self::history::History;
+pub use self::recent::RecentFiles;
 pub use self::searchbox::SearchBox;
+pub use self::statusbar::StatusBar;
+pub use self::tabs::{Document, Tabs};
+pub use self::windows::Windows;
@@ -0,0 +1,162 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+use crate::fuzzy;
+use gio::FileExt;
+use gtk::*;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// How many files the list shows at most.
+const MAX_RECENT: usize = 50;
+
+/// A dropdown of the files eddit opened or saved lately, as recorded by
+/// the desktop's recently used list, filtered by what is typed above it.
+#[derive(Clone)]
+pub struct RecentFiles {
+    pub button: MenuButton,
+    pub search_entry: SearchEntry,
+    pub list: ListBox,
+    /// The file of each row of `list`.
+    paths: Arc<RwLock<Vec<PathBuf>>>,
+}
+
+impl RecentFiles {
+    pub fn new() -> RecentFiles {
+        let button = MenuButton::new();
+        button.set_image(Some(&Image::new_from_icon_name(Some("document-open-recent"), IconSize::LargeToolbar)));
+        button.set_valign(Align::Center);
+        button.set_tooltip_text(Some("Recent files"));
+
+        let search_entry = SearchEntry::new();
+        search_entry.set_placeholder_text(Some("Filter recent files"));
+
+        let list = ListBox::new();
+        list.set_selection_mode(SelectionMode::Browse);
+        list.set_activate_on_single_click(true);
+        let placeholder = Label::new(Some("No recent files"));
+        placeholder.get_style_context().add_class(&STYLE_CLASS_DIM_LABEL);
+        placeholder.show();
+        list.set_placeholder(Some(&placeholder));
+
+        let scrolled = ScrolledWindow::new(None::<&Adjustment>, None::<&Adjustment>);
+        scrolled.set_policy(PolicyType::Never, PolicyType::Automatic);
+        scrolled.set_min_content_height(320);
+        scrolled.set_min_content_width(360);
+        scrolled.add(&list);
+
+        let container = Box::new(Orientation::Vertical, 6);
+        container.set_border_width(12);
+        container.pack_start(&search_entry, false, false, 0);
+        container.pack_start(&scrolled, true, true, 0);
+        container.show_all();
+
+        let popover = Popover::new(Some(&button));
+        popover.add(&container);
+        button.set_popover(Some(&popover));
+
+        RecentFiles {
+            button,
+            search_entry,
+            list,
+            paths: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    /// Lists the recent files matching the search entry, best match first,
+    /// or newest first while it is empty. Files that no longer exist are
+    /// dropped from the recently used list on the way.
+    pub fn refresh(&self) {
+        for row in self.list.get_children() {
+            self.list.remove(&row);
+        }
+
+        let query = self.search_entry.get_text().map(|text| text.to_string()).unwrap_or_default();
+        let mut files: Vec<(i32, i64, PathBuf)> = items()
+            .into_iter()
+            .filter_map(|(info, path)| {
+                let score = fuzzy::path_score(&query, &path)?;
+                Some((score, info.get_visited() as i64, path))
+            })
+            .collect();
+        files.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)));
+        files.truncate(MAX_RECENT);
+
+        for (_, _, path) in &files {
+            let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            let folder = path.parent().map(|parent| parent.to_string_lossy().into_owned()).unwrap_or_default();
+            let label = Label::new(None);
+            label.set_markup(&format!(
+                "{}\n<small>{}</small>",
+                glib::markup_escape_text(&name),
+                glib::markup_escape_text(&folder)
+            ));
+            label.set_halign(Align::Start);
+            label.set_ellipsize(pango::EllipsizeMode::Middle);
+            label.set_margin_top(3);
+            label.set_margin_bottom(3);
+            self.list.add(&label);
+        }
+        self.list.show_all();
+        self.list.select_row(self.list.get_row_at_index(0).as_ref());
+
+        *self.paths.write().unwrap() = files.into_iter().map(|(_, _, path)| path).collect();
+    }
+
+    /// The file of `row`.
+    pub fn path(&self, row: &ListBoxRow) -> Option<PathBuf> {
+        let index = row.get_index();
+        if index < 0 {
+            return None;
+        }
+        self.paths.read().unwrap().get(index as usize).cloned()
+    }
+}
+
+/// Records `path` in the recently used list as opened or saved by us.
+pub fn remember(path: &Path) {
+    if let Some(manager) = RecentManager::get_default() {
+        manager.add_item(&gio::File::new_for_path(path).get_uri());
+    }
+}
+
+/// The local files in the recently used list that eddit touched, after
+/// dropping those that no longer exist.
+fn items() -> Vec<(RecentInfo, PathBuf)> {
+    let manager = match RecentManager::get_default() {
+        Some(manager) => manager,
+        None => return Vec::new(),
+    };
+    let application = glib::get_prgname().unwrap_or_default();
+
+    manager
+        .get_items()
+        .into_iter()
+        .filter(|info| info.is_local() && info.has_application(&application))
+        .filter_map(|info| {
+            let uri = info.get_uri()?;
+            if !info.exists() {
+                let _ = manager.remove_item(&uri);
+                return None;
+            }
+            let path = gio::File::new_for_uri(&uri).get_path()?;
+            Some((info, path))
+        })
+        .collect()
+}
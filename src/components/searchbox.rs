@@ -17,8 +17,10 @@
  * Authored by: mazen <https://github.com/maze-n>
  */
 
+use super::History;
 use gtk::*;
 
+#[derive(Clone)]
 pub struct SearchBox {
     pub container: Box,
     pub search_entry: SearchEntry,
@@ -27,6 +29,13 @@ pub struct SearchBox {
     pub replace_all_button: Button,
     pub up: Button,
     pub down: Button,
+    pub matches: Label,
+    pub case_sensitive: ToggleButton,
+    pub whole_word: ToggleButton,
+    pub regex: ToggleButton,
+    pub in_selection: ToggleButton,
+    pub search_history: History,
+    pub replace_history: History,
 }
 
 impl SearchBox {
@@ -53,6 +62,31 @@ impl SearchBox {
         search_grid.add(&down);
         search_grid.add(&up);
 
+        let matches = Label::new(None);
+        matches.get_style_context().add_class(&STYLE_CLASS_DIM_LABEL);
+        matches.set_width_chars(12);
+
+        let options_grid = Grid::new();
+        options_grid
+            .get_style_context()
+            .add_class(&STYLE_CLASS_LINKED);
+        options_grid.set_border_width(4);
+        let case_sensitive = ToggleButton::new_with_label("Aa");
+        case_sensitive.set_tooltip_text(Some("Match case"));
+        let whole_word = ToggleButton::new_with_label("W");
+        whole_word.set_tooltip_text(Some("Match whole words only"));
+        let regex = ToggleButton::new_with_label(".*");
+        regex.set_tooltip_text(Some("Use regular expressions"));
+
+        options_grid.add(&case_sensitive);
+        options_grid.add(&whole_word);
+        let in_selection = ToggleButton::new();
+        in_selection.set_image(Some(&Image::new_from_icon_name(Some("edit-select-all-symbolic"), IconSize::SmallToolbar)));
+        in_selection.set_tooltip_text(Some("Search in the selection only"));
+
+        options_grid.add(&regex);
+        options_grid.add(&in_selection);
+
         let replace_grid = Grid::new();
         replace_grid
             .get_style_context()
@@ -70,7 +104,14 @@ impl SearchBox {
         replace_grid.add(&replace_button);
         replace_grid.add(&replace_all_button);
 
+        let search_history = History::new("search-history");
+        search_history.attach(&search_entry);
+        let replace_history = History::new("replace-history");
+        replace_history.attach(&replace_entry);
+
         container.add(&search_grid);
+        container.add(&matches);
+        container.add(&options_grid);
         container.add(&replace_grid);
 
         SearchBox {
@@ -81,6 +122,13 @@ impl SearchBox {
             replace_all_button,
             up,
             down,
+            matches,
+            case_sensitive,
+            whole_word,
+            regex,
+            in_selection,
+            search_history,
+            replace_history,
         }
     }
 }
@@ -0,0 +1,129 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+use crate::encoding;
+use crate::line_ending;
+use gtk::*;
+use pango::*;
+use sourceview::{LanguageExt, LanguageManager, LanguageManagerExt};
+
+/// Id of the language selector entry that turns highlighting off.
+pub const PLAIN_TEXT: &str = "plain-text";
+
+pub struct StatusBar {
+    pub container: Box,
+    pub path_label: Label,
+    pub encoding: ComboBoxText,
+    pub line_ending: ComboBoxText,
+    pub language: ComboBoxText,
+    pub position: Label,
+    pub position_button: Button,
+    pub indentation: MenuButton,
+    pub use_spaces: CheckButton,
+    pub indent_width: SpinButton,
+}
+
+impl StatusBar {
+    pub fn new() -> StatusBar {
+        let container = Box::new(Orientation::Horizontal, 6);
+        container.set_border_width(4);
+
+        let path_label = Label::new(Some("Unsaved file"));
+        path_label.set_selectable(true);
+        path_label.set_ellipsize(EllipsizeMode::Start);
+        path_label.set_halign(Align::Start);
+
+        let encoding = ComboBoxText::new();
+        encoding.set_tooltip_text(Some("Character encoding\nReopens the file when it has no unsaved changes,\notherwise applies on the next save"));
+        encoding.set_focus_on_click(false);
+        for choice in encoding::choices() {
+            encoding.append(Some(&choice.id()), &choice.label());
+        }
+
+        let line_ending = ComboBoxText::new();
+        line_ending.set_tooltip_text(Some("Line endings\nPick one to convert the whole document"));
+        line_ending.set_focus_on_click(false);
+        for choice in line_ending::CHOICES.iter() {
+            line_ending.append(Some(choice.id()), choice.label());
+        }
+
+        let language = ComboBoxText::new();
+        language.set_tooltip_text(Some("Syntax highlighting"));
+        language.set_focus_on_click(false);
+        language.append(Some(PLAIN_TEXT), "Plain Text");
+        if let Some(manager) = LanguageManager::get_default() {
+            let mut languages: Vec<(String, String)> = manager
+                .get_language_ids()
+                .iter()
+                .filter_map(|id| manager.get_language(id))
+                .filter(|language| !language.get_hidden())
+                .filter_map(|language| Some((language.get_id()?.to_string(), language.get_name()?.to_string())))
+                .collect();
+            languages.sort_by_key(|&(_, ref name)| name.to_lowercase());
+            for (id, name) in languages {
+                language.append(Some(&id), &name);
+            }
+        }
+
+        let position = Label::new(None);
+        let position_button = Button::new();
+        position_button.add(&position);
+        position_button.set_tooltip_text(Some("Cursor position and selection\nClick to go to a line"));
+        position_button.set_relief(ReliefStyle::None);
+        position_button.set_focus_on_click(false);
+
+        let indentation = MenuButton::new();
+        indentation.set_tooltip_text(Some("Indentation"));
+        indentation.set_relief(ReliefStyle::None);
+        indentation.set_focus_on_click(false);
+        let use_spaces = CheckButton::new_with_label("Insert spaces instead of tabs");
+        let indent_width = SpinButton::new_with_range(1.0, 16.0, 1.0);
+        let indent_width_box = Box::new(Orientation::Horizontal, 6);
+        indent_width_box.pack_start(&Label::new(Some("Width")), false, false, 0);
+        indent_width_box.pack_start(&indent_width, false, false, 0);
+        let indentation_box = Box::new(Orientation::Vertical, 6);
+        indentation_box.set_border_width(12);
+        indentation_box.pack_start(&use_spaces, false, false, 0);
+        indentation_box.pack_start(&indent_width_box, false, false, 0);
+        indentation_box.show_all();
+        let indentation_popover = Popover::new(Some(&indentation));
+        indentation_popover.add(&indentation_box);
+        indentation.set_popover(Some(&indentation_popover));
+
+        container.pack_start(&path_label, false, false, 0);
+        container.pack_end(&encoding, false, false, 0);
+        container.pack_end(&line_ending, false, false, 0);
+        container.pack_end(&language, false, false, 0);
+        container.pack_end(&indentation, false, false, 0);
+        container.pack_end(&position_button, false, false, 0);
+
+        StatusBar {
+            container,
+            path_label,
+            encoding,
+            line_ending,
+            language,
+            position,
+            position_button,
+            indentation,
+            use_spaces,
+            indent_width,
+        }
+    }
+}
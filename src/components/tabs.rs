@@ -0,0 +1,1036 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+use super::file_operations::{autosave, check_disk, open_dropped_files, save_before_close};
+use super::misc::*;
+use super::statusbar::PLAIN_TEXT;
+use super::{Content, Header, SearchBox, StatusBar, UnsavedDialog};
+use crate::encoding::TextEncoding;
+use crate::line_ending::LineEnding;
+use crate::recovery::{self, Snapshot};
+use crate::state::ActiveMetadata;
+use faccess::PathExt;
+use gio::{FileExt, FileMonitorExt, SettingsExt};
+use glib::{Cast, IsA, ObjectExt};
+use gtk::*;
+use sourceview::{
+    BufferExt, LanguageExt, LanguageManager, LanguageManagerExt, SearchContextExt, SearchSettingsExt, ViewExt,
+};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// How much of the text, in characters, the language guess looks at.
+const LANGUAGE_SAMPLE: i32 = 4096;
+
+static NEXT_DOCUMENT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A single open document: its editor, the file it is backed by and its tab.
+#[derive(Clone)]
+pub struct Document {
+    pub id: usize,
+    pub page: Box,
+    pub content: Content,
+    pub tab: Box,
+    pub tab_label: Label,
+    pub close_button: Button,
+    pub current_file: Arc<RwLock<Option<ActiveMetadata>>>,
+    pub modified: Arc<RwLock<bool>>,
+    info_bar: Arc<RwLock<Option<InfoBar>>>,
+    journaled: Arc<RwLock<bool>>,
+    /// Whether the last snapshot could not be written, so that the failure
+    /// is only reported once.
+    journal_failed: Arc<RwLock<bool>>,
+    autosave: Arc<RwLock<Option<glib::SourceId>>>,
+    monitor: Arc<RwLock<Option<gio::FileMonitor>>>,
+    loading: Arc<RwLock<Option<(PathBuf, Arc<AtomicBool>)>>>,
+    large: Arc<RwLock<bool>>,
+    binary: Arc<RwLock<Option<PathBuf>>>,
+    language_chosen: Arc<RwLock<bool>>,
+    search_scope: Arc<RwLock<Option<(TextMark, TextMark)>>>,
+    scoped_match: TextTag,
+    read_only: Arc<RwLock<bool>>,
+    closed: Arc<RwLock<Vec<std::boxed::Box<dyn Fn()>>>>,
+    /// The signal handlers on the document's own widgets. They hold on to
+    /// the document, so they are disconnected when it goes to let it be
+    /// freed.
+    handlers: Arc<RwLock<Vec<(glib::Object, glib::SignalHandlerId)>>>,
+}
+
+/// The notebook holding every open document, plus the window chrome that
+/// follows whichever document is active.
+#[derive(Clone)]
+pub struct Tabs {
+    pub notebook: Notebook,
+    documents: Arc<RwLock<Vec<Document>>>,
+    window: Window,
+    headerbar: HeaderBar,
+    save: Button,
+    path_label: Label,
+    encoding: ComboBoxText,
+    line_ending: ComboBoxText,
+    language: ComboBoxText,
+    position: Label,
+    indentation: MenuButton,
+    use_spaces: CheckButton,
+    indent_width: SpinButton,
+    matches: Label,
+    settings: gio::Settings,
+}
+
+impl Document {
+    fn new() -> Document {
+        let content = Content::new();
+
+        let page = Box::new(Orientation::Vertical, 0);
+        page.pack_start(&content.container, true, true, 0);
+
+        let tab = Box::new(Orientation::Horizontal, 4);
+        let tab_label = Label::new(Some("New file"));
+        let close_button = Button::new_from_icon_name(Some("window-close-symbolic"), IconSize::Menu);
+        close_button.set_relief(ReliefStyle::None);
+        close_button.set_tooltip_text(Some("Close\nCtrl + W"));
+        tab.pack_start(&tab_label, true, true, 0);
+        tab.pack_start(&close_button, false, false, 0);
+
+        let scoped_match = TextTag::new(Some("scoped-match"));
+        if let Some(table) = content.buff.get_tag_table() {
+            table.add(&scoped_match);
+        }
+
+        Document {
+            id: NEXT_DOCUMENT_ID.fetch_add(1, Ordering::SeqCst),
+            page,
+            content,
+            tab,
+            tab_label,
+            close_button,
+            current_file: Arc::new(RwLock::new(None)),
+            modified: Arc::new(RwLock::new(false)),
+            info_bar: Arc::new(RwLock::new(None)),
+            journaled: Arc::new(RwLock::new(true)),
+            journal_failed: Arc::new(RwLock::new(false)),
+            autosave: Arc::new(RwLock::new(None)),
+            monitor: Arc::new(RwLock::new(None)),
+            loading: Arc::new(RwLock::new(None)),
+            large: Arc::new(RwLock::new(false)),
+            binary: Arc::new(RwLock::new(None)),
+            language_chosen: Arc::new(RwLock::new(false)),
+            search_scope: Arc::new(RwLock::new(None)),
+            scoped_match,
+            read_only: Arc::new(RwLock::new(false)),
+            closed: Arc::new(RwLock::new(Vec::new())),
+            handlers: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    pub fn title(&self) -> String {
+        match self.path() {
+            Some(path) => path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.to_string_lossy().into_owned()),
+            None => String::from("New file"),
+        }
+    }
+
+    /// The file shown in the document, whether as text or as a hex dump.
+    pub fn path(&self) -> Option<PathBuf> {
+        match *self.current_file.read().unwrap() {
+            Some(ref file) => Some(file.get_path().to_path_buf()),
+            None => self.binary.read().unwrap().clone(),
+        }
+    }
+
+    pub fn is_modified(&self) -> bool {
+        *self.modified.read().unwrap()
+    }
+
+    pub fn set_modified(&self, modified: bool) {
+        let was_modified = std::mem::replace(&mut *self.modified.write().unwrap(), modified);
+        if was_modified && !modified {
+            recovery::remove(self.id);
+        }
+        self.refresh_label();
+    }
+
+    /// Writes the unsaved text to the recovery journal if it changed since
+    /// the last snapshot. Large documents are left out, as copying and
+    /// syncing them every few seconds would stall the editor.
+    pub fn journal(&self) {
+        if std::mem::replace(&mut *self.journaled.write().unwrap(), true) || self.is_large() {
+            return;
+        }
+
+        if !self.is_modified() {
+            recovery::remove(self.id);
+            return;
+        }
+
+        if let Some(text) = get_buffer(&self.content.buff) {
+            let snapshot = Snapshot {
+                path: self
+                    .current_file
+                    .read()
+                    .unwrap()
+                    .as_ref()
+                    .map(|file| file.get_path().to_path_buf()),
+                text: text.to_string(),
+            };
+            let result = recovery::save(self.id, &snapshot);
+            let failed_before = std::mem::replace(&mut *self.journal_failed.write().unwrap(), result.is_err());
+            if let Err(error) = result {
+                if !failed_before {
+                    let message = format!("Unsaved changes cannot be backed up for recovery: {}", error);
+                    self.show_message(MessageType::Warning, &message);
+                }
+            }
+        }
+    }
+
+    /// Keeps `handler`, connected on `object`, to disconnect in `discard`.
+    fn hold_handler<O: IsA<glib::Object>>(&self, object: &O, handler: glib::SignalHandlerId) {
+        self.handlers.write().unwrap().push((object.clone().upcast(), handler));
+    }
+
+    /// Cancels any pending autosave, stops loading or watching the file,
+    /// disconnects the document's handlers and drops its snapshot, for when
+    /// it is closed.
+    fn discard(&self) {
+        if let Some(source) = self.autosave.write().unwrap().take() {
+            glib::source_remove(source);
+        }
+        if let Some(monitor) = self.monitor.write().unwrap().take() {
+            monitor.cancel();
+        }
+        self.cancel_loading();
+        for (object, handler) in self.handlers.write().unwrap().drain(..) {
+            object.disconnect(handler);
+        }
+        recovery::remove(self.id);
+    }
+
+    /// Recomputes the dirty state from the buffer's modified flag, which any
+    /// edit sets and loading or saving clears, and from a pending change of
+    /// encoding or line ending. Documents without a file are only dirty
+    /// while they contain text.
+    pub fn update_modified(&self) {
+        let edited = self.content.buff.get_modified();
+        let modified = match *self.current_file.read().unwrap() {
+            Some(ref file) => edited || !file.is_format_saved(),
+            None => edited && self.content.buff.get_char_count() > 0,
+        };
+        self.set_modified(modified);
+    }
+
+    /// Marks the text as being what was last loaded or saved.
+    pub fn set_clean(&self) {
+        self.content.buff.set_modified(false);
+        self.update_modified();
+    }
+
+    /// Checks whether an undo or redo took the text back to what was last
+    /// loaded or saved, by comparing it against the saved sum. Large
+    /// documents skip the check and stay modified.
+    fn confirm_modified(&self) {
+        if !self.content.buff.get_modified() || self.is_large() {
+            return;
+        }
+
+        if let Some(text) = get_buffer(&self.content.buff) {
+            let clean = match *self.current_file.read().unwrap() {
+                Some(ref file) => file.has_same_text(&text.as_bytes()),
+                None => text.is_empty(),
+            };
+            if clean {
+                self.set_clean();
+            }
+        }
+    }
+
+    /// An untouched "New file" document that can be reused when opening a file.
+    pub fn is_blank(&self) -> bool {
+        self.current_file.read().unwrap().is_none()
+            && !self.is_modified()
+            && !self.is_loading()
+            && !self.is_binary()
+            && self.content.buff.get_char_count() == 0
+    }
+
+    /// Shows `dump` of the binary file `path` read-only in place of the
+    /// editor, or brings the editor back when `path` is `None`.
+    pub fn set_binary(&self, path: Option<PathBuf>, dump: &str) {
+        match path {
+            Some(_) => self.content.show_hex(dump),
+            None => self.content.show_text(),
+        }
+        *self.binary.write().unwrap() = path;
+        self.refresh_label();
+    }
+
+    pub fn is_binary(&self) -> bool {
+        self.binary.read().unwrap().is_some()
+    }
+
+    /// Marks the document as being filled from `path` in the background and
+    /// locks the text until then. Returns the flag that cancels the load.
+    pub fn start_loading(&self, path: &Path) -> Arc<AtomicBool> {
+        let canceled = Arc::new(AtomicBool::new(false));
+        *self.loading.write().unwrap() = Some((path.to_path_buf(), canceled.clone()));
+        self.content.view.set_editable(false);
+        canceled
+    }
+
+    pub fn finish_loading(&self) {
+        *self.loading.write().unwrap() = None;
+        self.content.view.set_editable(true);
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading.read().unwrap().is_some()
+    }
+
+    pub fn cancel_loading(&self) {
+        if let Some((_, ref canceled)) = *self.loading.read().unwrap() {
+            canceled.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Switches off what gets too slow on huge files: syntax highlighting,
+    /// line wrapping and hashing the whole text after an undo, which leaves
+    /// the document modified after any edit.
+    pub fn enable_large_mode(&self) {
+        *self.large.write().unwrap() = true;
+        self.content.buff.set_highlight_syntax(false);
+        self.content.view.set_wrap_mode(WrapMode::None);
+    }
+
+    pub fn is_large(&self) -> bool {
+        *self.large.read().unwrap()
+    }
+
+    /// Shows `message` in an info bar above the text, replacing any message
+    /// already there. Callers add their own buttons to the returned bar; the
+    /// close button dismisses it.
+    pub fn show_message(&self, message_type: MessageType, message: &str) -> InfoBar {
+        self.hide_message();
+
+        let info_bar = InfoBar::new();
+        info_bar.set_message_type(message_type);
+        info_bar.set_show_close_button(true);
+
+        let label = Label::new(Some(message));
+        label.set_line_wrap(true);
+        label.set_xalign(0.0);
+        if let Some(area) = info_bar.get_content_area().and_then(|area| area.downcast::<Box>().ok()) {
+            area.pack_start(&label, true, true, 0);
+        }
+
+        let info_bar_slot = self.info_bar.clone();
+        info_bar.connect_response(move |info_bar, response| {
+            if response == ResponseType::Close {
+                info_bar.destroy();
+                *info_bar_slot.write().unwrap() = None;
+            }
+        });
+
+        self.page.pack_start(&info_bar, false, false, 0);
+        self.page.reorder_child(&info_bar, 0);
+        info_bar.show_all();
+
+        *self.info_bar.write().unwrap() = Some(info_bar.clone());
+        info_bar
+    }
+
+    pub fn hide_message(&self) {
+        if let Some(info_bar) = self.info_bar.write().unwrap().take() {
+            info_bar.destroy();
+        }
+    }
+
+    /// The id of the language the document is highlighted as, if any.
+    pub fn language_id(&self) -> Option<String> {
+        self.content
+            .buff
+            .get_language()
+            .and_then(|language| language.get_id())
+            .map(|id| id.to_string())
+    }
+
+    /// Picks the highlighting from the file name and the start of the text,
+    /// unless a language was chosen by hand.
+    pub fn guess_language(&self) {
+        if *self.language_chosen.read().unwrap() {
+            return;
+        }
+
+        let manager = match LanguageManager::get_default() {
+            Some(manager) => manager,
+            None => return,
+        };
+        let name = self
+            .path()
+            .and_then(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()));
+        let buff = &self.content.buff;
+        let sample = buff
+            .get_text(&buff.get_start_iter(), &buff.get_iter_at_offset(LANGUAGE_SAMPLE), false)
+            .map(|sample| sample.to_string())
+            .unwrap_or_default();
+        let (content_type, _) = gio::content_type_guess(name.as_ref().map(String::as_str), sample.as_bytes());
+        let language = manager.guess_language(name.as_ref().map(String::as_str), Some(content_type.as_str()));
+        buff.set_language(language.as_ref());
+    }
+
+    /// Highlights the document as the language `id`, or as plain text when
+    /// there is no such language, overriding the guess from then on.
+    pub fn choose_language(&self, id: &str) {
+        let language = LanguageManager::get_default().and_then(|manager| manager.get_language(id));
+        if language.as_ref().and_then(|language| language.get_id()).map(|id| id.to_string()) == self.language_id() {
+            return;
+        }
+
+        *self.language_chosen.write().unwrap() = true;
+        self.content.buff.set_language(language.as_ref());
+    }
+
+    /// Stops the text from being edited or saved in place.
+    pub fn set_read_only(&self, read_only: bool) {
+        *self.read_only.write().unwrap() = read_only;
+        self.content.view.set_editable(!read_only);
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        *self.read_only.read().unwrap()
+    }
+
+    /// Runs `f` once the tab of the document has been closed.
+    pub fn connect_closed<F: Fn() + 'static>(&self, f: F) {
+        self.closed.write().unwrap().push(std::boxed::Box::new(f));
+    }
+
+    /// Runs, and forgets, the functions given to `connect_closed`: when the
+    /// tab is closed, or the whole window.
+    pub fn notify_closed(&self) {
+        let closed: Vec<_> = self.closed.write().unwrap().drain(..).collect();
+        for closed in closed {
+            closed();
+        }
+    }
+
+    /// Puts the cursor on `line` at `column`, both counted from 0 and kept
+    /// within the text, and scrolls it into view.
+    pub fn go_to(&self, line: i32, column: i32) {
+        let buff = &self.content.buff;
+        let mut iter = buff.get_iter_at_line(line.max(0).min(buff.get_line_count() - 1));
+        let mut line_end = iter.clone();
+        if !line_end.ends_line() {
+            line_end.forward_to_line_end();
+        }
+        iter.set_line_offset(column.max(0).min(line_end.get_line_offset()));
+
+        buff.place_cursor(&iter);
+        if let Some(insert) = buff.get_insert() {
+            self.content.view.scroll_to_mark(&insert, 0.0, true, 0.0, 0.5);
+        }
+        self.content.view.grab_focus();
+    }
+
+    /// Confines searching to the text between `start` and `end`, or lifts
+    /// the restriction when `range` is `None`. The search context can only
+    /// highlight the whole buffer, so matches in a scope are highlighted
+    /// with a tag of our own instead.
+    pub fn set_search_scope(&self, range: Option<(&TextIter, &TextIter)>) {
+        let buff = &self.content.buff;
+        if let Some((start, end)) = self.search_scope.write().unwrap().take() {
+            buff.delete_mark(&start);
+            buff.delete_mark(&end);
+        }
+        if let Some((start, end)) = range {
+            // The gravities let the scope grow with text typed or replaced
+            // at either edge.
+            let start_mark = TextMark::new(None, true);
+            let end_mark = TextMark::new(None, false);
+            buff.add_mark(&start_mark, start);
+            buff.add_mark(&end_mark, end);
+            *self.search_scope.write().unwrap() = Some((start_mark, end_mark));
+        }
+        self.content.search_context.set_highlight(range.is_none());
+        self.highlight_scope();
+    }
+
+    pub fn search_scope(&self) -> Option<(TextIter, TextIter)> {
+        let buff = &self.content.buff;
+        self.search_scope
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|(start, end)| (buff.get_iter_at_mark(start), buff.get_iter_at_mark(end)))
+    }
+
+    /// The first match in the search scope, or in the whole buffer when
+    /// there is no scope.
+    pub fn first_match(&self) -> Option<(TextIter, TextIter)> {
+        let start = self
+            .search_scope()
+            .map_or_else(|| self.content.buff.get_start_iter(), |(start, _)| start);
+        self.find_forward(&start, true)
+    }
+
+    /// The first match after `iter`. A regex can match the empty string, so
+    /// an empty match right at `iter` is stepped over to keep navigation
+    /// moving.
+    pub fn next_match(&self, iter: &TextIter) -> Option<(TextIter, TextIter)> {
+        self.find_forward(iter, false)
+    }
+
+    /// The last match before `iter`, stepping over an empty match at `iter`.
+    pub fn previous_match(&self, iter: &TextIter) -> Option<(TextIter, TextIter)> {
+        let context = &self.content.search_context;
+        let scope = self.search_scope();
+        let mut from = iter.clone();
+        if let Some((_, ref end)) = scope {
+            if from > *end {
+                from = end.clone();
+            }
+        }
+
+        let found = match context.backward(&from) {
+            Some((start, end)) if start == end && end == from => {
+                let mut previous = from.clone();
+                if previous.backward_char() {
+                    context.backward(&previous)
+                } else {
+                    None
+                }
+            }
+            found => found,
+        };
+        match (found, scope) {
+            (Some((start, _)), Some((limit, _))) if start < limit => None,
+            (found, _) => found,
+        }
+    }
+
+    fn find_forward(&self, iter: &TextIter, allow_empty: bool) -> Option<(TextIter, TextIter)> {
+        let context = &self.content.search_context;
+        let scope = self.search_scope();
+        let mut from = iter.clone();
+        if let Some((ref start, _)) = scope {
+            if from < *start {
+                from = start.clone();
+            }
+        }
+
+        let found = match context.forward(&from) {
+            Some((start, end)) if !allow_empty && start == end && start == from => {
+                let mut next = from.clone();
+                if next.forward_char() {
+                    context.forward(&next)
+                } else {
+                    None
+                }
+            }
+            found => found,
+        };
+        match (found, scope) {
+            (Some((_, end)), Some((_, limit))) if end > limit => None,
+            (found, _) => found,
+        }
+    }
+
+    /// Every match in the search scope; empty when there is no scope.
+    pub fn scope_matches(&self) -> Vec<(TextIter, TextIter)> {
+        let mut matches = Vec::new();
+        if self.search_scope().is_some() {
+            let mut found = self.first_match();
+            while let Some((start, end)) = found {
+                found = self.next_match(&end);
+                matches.push((start, end));
+            }
+        }
+        matches
+    }
+
+    /// Re-applies the highlighting of matches in the search scope, in the
+    /// colors the style scheme uses for search matches.
+    pub fn highlight_scope(&self) {
+        let buff = &self.content.buff;
+        let (start, end) = buff.get_bounds();
+        buff.remove_tag(&self.scoped_match, &start, &end);
+
+        if let Some(style) = self.content.search_context.get_match_style() {
+            let background = style.get_property_background();
+            let foreground = style.get_property_foreground();
+            self.scoped_match
+                .set_property_background(background.as_ref().map(|color| color.as_str()));
+            self.scoped_match
+                .set_property_foreground(foreground.as_ref().map(|color| color.as_str()));
+        }
+        for (start, end) in self.scope_matches() {
+            buff.apply_tag(&self.scoped_match, &start, &end);
+        }
+    }
+
+    pub fn refresh_label(&self) {
+        let title = self.title();
+        if self.is_modified() {
+            self.tab_label.set_text(&format!("*{}", title));
+        } else {
+            self.tab_label.set_text(&title);
+        }
+
+        match self.path() {
+            Some(path) => self.tab.set_tooltip_text(Some(&path.to_string_lossy())),
+            None => self.tab.set_tooltip_text(None),
+        }
+    }
+}
+
+impl Tabs {
+    pub fn new(window: &Window, header: &Header, status_bar: &StatusBar, search_bar: &SearchBox) -> Tabs {
+        let notebook = Notebook::new();
+        notebook.set_scrollable(true);
+        notebook.set_show_border(false);
+
+        let tabs = Tabs {
+            notebook,
+            documents: Arc::new(RwLock::new(Vec::new())),
+            window: window.clone(),
+            headerbar: header.container.clone(),
+            save: header.save.clone(),
+            path_label: status_bar.path_label.clone(),
+            encoding: status_bar.encoding.clone(),
+            line_ending: status_bar.line_ending.clone(),
+            language: status_bar.language.clone(),
+            position: status_bar.position.clone(),
+            indentation: status_bar.indentation.clone(),
+            use_spaces: status_bar.use_spaces.clone(),
+            indent_width: status_bar.indent_width.clone(),
+            matches: search_bar.matches.clone(),
+            settings: gio::Settings::new("com.github.maze-n.eddit"),
+        };
+
+        let tabs_clone = tabs.clone();
+        tabs.notebook.connect_property_page_notify(move |_| tabs_clone.refresh());
+
+        tabs
+    }
+
+    /// Opens a new, empty document in its own tab and makes it active.
+    pub fn add(&self) -> Document {
+        let document = Document::new();
+
+        let buff = &document.content.buff;
+        let tabs = self.clone();
+        let document_clone = document.clone();
+        let handler = buff.connect_changed(move |_| {
+            if document_clone.is_loading() {
+                return;
+            }
+
+            document_clone.update_modified();
+            if document_clone.search_scope().is_some() {
+                document_clone.highlight_scope();
+            }
+            *document_clone.journaled.write().unwrap() = false;
+            tabs.schedule_autosave(&document_clone);
+            if tabs.is_active(&document_clone) {
+                tabs.refresh();
+            }
+        });
+        document.hold_handler(buff, handler);
+
+        let tabs = self.clone();
+        let document_clone = document.clone();
+        let handler = buff.connect_mark_set(move |buff, _, mark| {
+            let moved = buff.get_insert().map_or(false, |insert| &insert == mark)
+                || buff.get_selection_bound().map_or(false, |bound| &bound == mark);
+            if moved && tabs.is_active(&document_clone) {
+                tabs.refresh_position(&document_clone);
+                tabs.refresh_matches(&document_clone);
+            }
+        });
+        document.hold_handler(buff, handler);
+
+        let tabs = self.clone();
+        let document_clone = document.clone();
+        let search_context = &document.content.search_context;
+        let handler = search_context.connect_property_occurrences_count_notify(move |_| {
+            if tabs.is_active(&document_clone) {
+                tabs.refresh_matches(&document_clone);
+            }
+        });
+        document.hold_handler(search_context, handler);
+
+        // The flag is only raised after the "changed" handlers have run.
+        let tabs = self.clone();
+        let document_clone = document.clone();
+        let handler = buff.connect_modified_changed(move |_| {
+            if document_clone.is_loading() {
+                return;
+            }
+
+            document_clone.update_modified();
+            tabs.schedule_autosave(&document_clone);
+            if tabs.is_active(&document_clone) {
+                tabs.refresh();
+            }
+        });
+        document.hold_handler(buff, handler);
+
+        let document_clone = document.clone();
+        let handler = buff.connect_undo(move |_| {
+            let document = document_clone.clone();
+            glib::idle_add_local(move || {
+                document.confirm_modified();
+                glib::Continue(false)
+            });
+        });
+        document.hold_handler(buff, handler);
+
+        let document_clone = document.clone();
+        let handler = buff.connect_redo(move |_| {
+            let document = document_clone.clone();
+            glib::idle_add_local(move || {
+                document.confirm_modified();
+                glib::Continue(false)
+            });
+        });
+        document.hold_handler(buff, handler);
+
+        let tabs = self.clone();
+        let document_clone = document.clone();
+        let handler = document.close_button.connect_clicked(move |_| {
+            tabs.close(&document_clone);
+        });
+        document.hold_handler(&document.close_button, handler);
+
+        open_dropped_files(self, &self.window, &document.content.view);
+
+        self.documents.write().unwrap().push(document.clone());
+
+        document.page.show_all();
+        document.tab.show_all();
+        let page = self.notebook.append_page(&document.page, Some(&document.tab));
+        self.notebook.set_tab_reorderable(&document.page, true);
+        self.notebook.set_current_page(Some(page));
+        document.content.view.grab_focus();
+        self.refresh();
+
+        document
+    }
+
+    pub fn documents(&self) -> Vec<Document> {
+        self.documents.read().unwrap().clone()
+    }
+
+    pub fn active(&self) -> Option<Document> {
+        let current = self.notebook.get_current_page()?;
+        self.documents()
+            .into_iter()
+            .find(|document| self.notebook.page_num(&document.page) == Some(current))
+    }
+
+    pub fn is_active(&self, document: &Document) -> bool {
+        let current = self.notebook.get_current_page();
+        current.is_some() && self.notebook.page_num(&document.page) == current
+    }
+
+    /// Finds the tab that already has `path` open, or is loading it, if any.
+    pub fn find(&self, path: &Path) -> Option<Document> {
+        self.documents().into_iter().find(|document| {
+            match document.path() {
+                Some(ref open) => open == path,
+                None => match *document.loading.read().unwrap() {
+                    Some((ref loading, _)) => loading == path,
+                    None => false,
+                },
+            }
+        })
+    }
+
+    /// The document a newly opened file should be loaded into: the active tab
+    /// when it is still blank, a fresh tab otherwise.
+    pub fn target(&self) -> Document {
+        match self.active() {
+            Some(ref document) if document.is_blank() => document.clone(),
+            _ => self.add(),
+        }
+    }
+
+    pub fn present(&self, document: &Document) {
+        if let Some(page) = self.notebook.page_num(&document.page) {
+            self.notebook.set_current_page(Some(page));
+        }
+        document.content.view.grab_focus();
+    }
+
+    /// Updates the header subtitle, status bar and save button for the
+    /// active document.
+    pub fn refresh(&self) {
+        self.notebook.set_show_tabs(self.notebook.get_n_pages() > 1);
+
+        if let Some(document) = self.active() {
+            // Setting the combos runs their "changed" handlers, which may
+            // lock the file state themselves, so it is copied out first.
+            let file = document
+                .current_file
+                .read()
+                .unwrap()
+                .as_ref()
+                .map(|file| (file.get_path().to_path_buf(), file.get_encoding(), file.get_line_ending()));
+            match file {
+                Some((path, encoding, line_ending)) => {
+                    if path.writable() && !document.is_read_only() {
+                        self.path_label.set_text(&path.to_string_lossy());
+                    } else {
+                        self.path_label.set_text(&format!("{} - [READ ONLY]", &path.to_string_lossy()));
+                    }
+                    self.encoding.set_active_id(Some(&encoding.id()));
+                    self.encoding.set_sensitive(true);
+                    self.line_ending.set_active_id(Some(line_ending.id()));
+                    self.line_ending.set_sensitive(true);
+                }
+                None => {
+                    let binary = document.binary.read().unwrap().clone();
+                    match binary {
+                        Some(path) => self.path_label.set_text(&format!("{} - [BINARY]", path.to_string_lossy())),
+                        None => self.path_label.set_text("Unsaved file"),
+                    }
+                    self.encoding.set_active_id(Some(&TextEncoding::utf8().id()));
+                    self.encoding.set_sensitive(false);
+                    self.line_ending.set_active_id(Some(LineEnding::Lf.id()));
+                    self.line_ending.set_sensitive(false);
+                }
+            }
+            let language = document.language_id().unwrap_or_else(|| PLAIN_TEXT.to_string());
+            self.language.set_active_id(Some(&language));
+            self.language.set_sensitive(!document.is_binary());
+
+            let view = &document.content.view;
+            let spaces = view.get_insert_spaces_instead_of_tabs();
+            let width = view.get_tab_width();
+            self.use_spaces.set_active(spaces);
+            self.indent_width.set_value(width as f64);
+            self.indentation.set_label(&if spaces {
+                format!("Spaces: {}", width)
+            } else {
+                format!("Tab Width: {}", width)
+            });
+            self.indentation.set_sensitive(!document.is_binary());
+
+            self.refresh_position(&document);
+            self.refresh_matches(&document);
+            self.headerbar.set_subtitle(Some(&document.title()));
+            self.save.set_sensitive(document.is_modified());
+        }
+    }
+
+    /// Shows where the cursor of `document` is, and how much is selected.
+    fn refresh_position(&self, document: &Document) {
+        if document.is_binary() {
+            self.position.set_text("");
+            return;
+        }
+
+        let buff = &document.content.buff;
+        let cursor = match buff.get_insert() {
+            Some(insert) => buff.get_iter_at_mark(&insert),
+            None => return,
+        };
+        let mut position = format!(
+            "Ln {}, Col {}",
+            cursor.get_line() + 1,
+            document.content.view.get_visual_column(&cursor) + 1
+        );
+        if let Some((start, end)) = buff.get_selection_bounds() {
+            let lines = end.get_line() - start.get_line() + 1;
+            position.push_str(&format!(
+                " ({} selected, {} {})",
+                end.get_offset() - start.get_offset(),
+                lines,
+                if lines == 1 { "line" } else { "lines" }
+            ));
+        }
+        self.position.set_text(&position);
+    }
+
+    /// Shows how many times the search query occurs in `document`, and which
+    /// of them is selected.
+    fn refresh_matches(&self, document: &Document) {
+        let context = &document.content.search_context;
+        let searching = document
+            .content
+            .search_settings
+            .get_search_text()
+            .map_or(false, |text| !text.is_empty());
+        // The count is -1 while the buffer is still being scanned; the
+        // notify handler comes back here once it is known.
+        let count = context.get_occurrences_count();
+        if !searching || count < 0 {
+            self.matches.set_text("");
+            return;
+        }
+
+        let selection = document.content.buff.get_selection_bounds();
+        let (count, position) = if document.search_scope().is_some() {
+            let matches = document.scope_matches();
+            let position = selection
+                .and_then(|selection| matches.iter().position(|found| *found == selection))
+                .map_or(0, |index| index as i32 + 1);
+            (matches.len() as i32, position)
+        } else {
+            let position = selection
+                .map_or(0, |(start, end)| context.get_occurrence_position(&start, &end))
+                .max(0);
+            (count, position)
+        };
+        self.matches.set_text(&match (count, position) {
+            (0, _) => "No matches".to_string(),
+            (1, 0) => "1 match".to_string(),
+            (count, 0) => format!("{} matches", count),
+            (count, position) => format!("{} of {}", position, count),
+        });
+    }
+
+    /// Restarts the idle countdown after which `document` is saved, when
+    /// autosave is enabled and the document has a file to save to.
+    fn schedule_autosave(&self, document: &Document) {
+        if let Some(source) = document.autosave.write().unwrap().take() {
+            glib::source_remove(source);
+        }
+
+        if !self.settings.get_boolean("autosave")
+            || !document.is_modified()
+            || document.current_file.read().unwrap().is_none()
+        {
+            return;
+        }
+
+        let interval = self.settings.get_int("autosave-interval").max(1) as u32;
+        let tabs = self.clone();
+        let document_clone = document.clone();
+        let source = glib::timeout_add_seconds_local(interval, move || {
+            document_clone.autosave.write().unwrap().take();
+            autosave(&tabs, &document_clone, &tabs.window);
+            glib::Continue(false)
+        });
+        *document.autosave.write().unwrap() = Some(source);
+    }
+
+    /// Starts watching the file behind `document` for changes made by other
+    /// programs, replacing the watch on any file it had before.
+    pub fn watch(&self, document: &Document) {
+        let monitor = match *document.current_file.read().unwrap() {
+            Some(ref file) => gio::File::new_for_path(file.get_path())
+                .monitor_file(gio::FileMonitorFlags::NONE, gio::NONE_CANCELLABLE)
+                .ok(),
+            None => None,
+        };
+
+        if let Some(ref monitor) = monitor {
+            let tabs = self.clone();
+            let document_clone = document.clone();
+            monitor.connect_changed(move |_, _, _, event| match event {
+                gio::FileMonitorEvent::ChangesDoneHint
+                | gio::FileMonitorEvent::Created
+                | gio::FileMonitorEvent::Deleted => check_disk(&tabs, &document_clone, &tabs.window),
+                _ => (),
+            });
+        }
+
+        if let Some(old) = std::mem::replace(&mut *document.monitor.write().unwrap(), monitor) {
+            old.cancel();
+        }
+    }
+
+    /// Asks whether to save `document` if it has unsaved changes. Returns
+    /// false when the user cancels or the save fails.
+    pub fn confirm_close(&self, document: &Document) -> bool {
+        if !document.is_modified() {
+            return true;
+        }
+
+        self.present(document);
+        let dialog = UnsavedDialog::new(&self.window);
+        let result = dialog.run();
+        if result == ResponseType::Yes.into() {
+            let is_saved = save_before_close(self, document, &self.window);
+            self.refresh();
+            is_saved
+        } else {
+            result == ResponseType::No.into()
+        }
+    }
+
+    /// Closes `document` after the unsaved changes prompt. The last tab is
+    /// replaced by a new empty document rather than leaving the window bare.
+    pub fn close(&self, document: &Document) -> bool {
+        if !self.confirm_close(document) {
+            return false;
+        }
+
+        document.discard();
+        self.documents
+            .write()
+            .unwrap()
+            .retain(|other| other.page != document.page);
+        if let Some(page) = self.notebook.page_num(&document.page) {
+            self.notebook.remove_page(Some(page));
+        }
+
+        if self.notebook.get_n_pages() == 0 {
+            self.add();
+        } else {
+            self.refresh();
+        }
+
+        document.notify_closed();
+        true
+    }
+
+    /// Runs the unsaved changes prompt for every document, stopping at the
+    /// first one the user cancels.
+    pub fn confirm_close_all(&self) -> bool {
+        let confirmed = self
+            .documents()
+            .iter()
+            .all(|document| self.confirm_close(document));
+        if confirmed {
+            self.discard_all();
+        }
+        confirmed
+    }
+
+    /// Lets go of every document without asking, for when the session has
+    /// kept what they hold or their window is gone.
+    pub fn discard_all(&self) {
+        for document in self.documents() {
+            document.discard();
+        }
+    }
+
+    /// Snapshots every document with unsaved changes into the recovery
+    /// journal.
+    pub fn journal(&self) {
+        for document in self.documents() {
+            document.journal();
+        }
+    }
+}
@@ -0,0 +1,224 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+use super::file_operations::*;
+use super::{App, ConnectedApp, Tabs};
+use crate::cli::{self, Command, FileArg, Options};
+use gio::prelude::ApplicationExtManual;
+use gio::{
+    ActionMapExt, ApplicationCommandLine, ApplicationCommandLineExt, ApplicationExt, ApplicationFlags, FileExt,
+    InputStream, InputStreamExt, SettingsExt,
+};
+use gtk::*;
+use std::cell::RefCell;
+use std::env;
+use std::ffi::OsString;
+use std::io;
+use std::sync::{Arc, RwLock};
+
+pub const APPLICATION_ID: &str = "com.github.maze-n.eddit";
+
+/// The windows of the running instance. Later launches hand their command
+/// line to it over D-Bus, and their files open in the window that was used
+/// last, or in a new one.
+#[derive(Clone)]
+pub struct Windows {
+    application: Application,
+    apps: Arc<RwLock<Vec<ConnectedApp>>>,
+}
+
+impl Windows {
+    pub fn new() -> Result<Windows, glib::BoolError> {
+        let application = Application::new(
+            Some(APPLICATION_ID),
+            ApplicationFlags::HANDLES_OPEN | ApplicationFlags::HANDLES_COMMAND_LINE,
+        )?;
+        let windows = Windows {
+            application,
+            apps: Arc::new(RwLock::new(Vec::new())),
+        };
+
+        let windows_clone = windows.clone();
+        windows
+            .application
+            .connect_command_line(move |_, command_line| windows_clone.command_line(command_line));
+
+        let windows_clone = windows.clone();
+        windows
+            .application
+            .connect_open(move |_, files, _| windows_clone.open(files));
+
+        let windows_clone = windows.clone();
+        windows.application.connect_activate(move |_| {
+            windows_clone.target(false).1.present();
+        });
+
+        // Ctrl+Shift+N in any window activates this.
+        let new_window = gio::SimpleAction::new("new-window", None);
+        let windows_clone = windows.clone();
+        new_window.connect_activate(move |_, _| {
+            windows_clone.open_window();
+        });
+        windows.application.add_action(&new_window);
+
+        Ok(windows)
+    }
+
+    /// Runs the application with the arguments of this process, either as
+    /// the primary instance or by passing them on to it, and returns the
+    /// exit status.
+    pub fn run(&self) -> i32 {
+        let args: Vec<String> = env::args().collect();
+        self.application.run(&args)
+    }
+
+    /// Opens a new window. The first one also offers to recover what a
+    /// crashed session left unsaved.
+    pub fn open_window(&self) -> (Tabs, Window) {
+        let app = App::new(&self.application).connect_events();
+        let tabs = app.tabs().clone();
+        let window = app.window().clone();
+
+        // Whoever waits on the documents of a closed window stops waiting.
+        let apps = self.apps.clone();
+        let tabs_clone = tabs.clone();
+        window.connect_destroy(move |window| {
+            apps.write().unwrap().retain(|app| app.window() != window);
+            tabs_clone.discard_all();
+            for document in tabs_clone.documents() {
+                document.notify_closed();
+            }
+        });
+
+        if self.apps.read().unwrap().is_empty() {
+            recover(&tabs, &window);
+        }
+        app.show();
+        self.apps.write().unwrap().push(app);
+        (tabs, window)
+    }
+
+    /// The window to open files in: the active one, unless `new_window` is
+    /// set or there is none yet.
+    fn target(&self, new_window: bool) -> (Tabs, Window) {
+        if !new_window {
+            let active = self.application.get_active_window();
+            let apps = self.apps.read().unwrap();
+            let app = apps
+                .iter()
+                .find(|app| active.as_ref() == Some(app.window()))
+                .or_else(|| apps.last());
+            if let Some(app) = app {
+                return (app.tabs().clone(), app.window().clone());
+            }
+        }
+        self.open_window()
+    }
+
+    /// Handles the arguments of a launch, ours or a later one. Help, version
+    /// and mistakes were dealt with by the launching process already.
+    fn command_line(&self, command_line: &ApplicationCommandLine) -> i32 {
+        let cwd = command_line
+            .get_cwd()
+            .or_else(|| env::current_dir().ok())
+            .unwrap_or_default();
+        let args = command_line.get_arguments().into_iter().skip(1).map(local_path);
+        let options = match cli::parse(args, &cwd) {
+            Ok(Command::Run(options)) => options,
+            _ => return 2,
+        };
+
+        let (tabs, window) = self.target(options.new_window);
+        // Only the launch that started the editor brings the last session
+        // back, and not when it asked for a new, empty window.
+        let settings = gio::Settings::new("com.github.maze-n.eddit");
+        if !command_line.get_is_remote()
+            && !options.new_window
+            && options.files.is_empty()
+            && !options.stdin
+            && settings.get_boolean("restore-session")
+        {
+            restore_session(&tabs, &window);
+        }
+
+        let stdin = if options.stdin {
+            Some(read_stream(command_line.get_stdin()))
+        } else {
+            None
+        };
+
+        // With --wait, a launch from elsewhere waits for as long as its
+        // command line is held on to. Our own launch is the editor itself and
+        // returns when it quits; closing windows here would take documents
+        // that have nothing to do with the command along.
+        let waiting = RefCell::new(Some(command_line.clone()).filter(|_| options.wait));
+        open_from_command_line(&tabs, &window, &options, stdin, move || {
+            waiting.borrow_mut().take();
+        });
+        window.present();
+        0
+    }
+
+    /// Opens files handed over by the desktop, such as from a file manager.
+    fn open(&self, files: &[gio::File]) {
+        let files = files
+            .iter()
+            .filter_map(|file| file.get_path())
+            .map(|path| FileArg {
+                path,
+                line: None,
+                column: None,
+            })
+            .collect();
+        let options = Options {
+            files,
+            ..Options::default()
+        };
+
+        let (tabs, window) = self.target(false);
+        open_from_command_line(&tabs, &window, &options, None, || ());
+        window.present();
+    }
+}
+
+/// The desktop file passes `file://` URIs; anything else is taken as a path.
+fn local_path(arg: OsString) -> String {
+    let arg = arg.to_string_lossy().into_owned();
+    if arg.starts_with("file://") {
+        if let Some(path) = gio::File::new_for_uri(&arg).get_path() {
+            return path.to_string_lossy().into_owned();
+        }
+    }
+    arg
+}
+
+/// Reads everything from the standard input of the launching process.
+fn read_stream(stream: Option<InputStream>) -> io::Result<Vec<u8>> {
+    let stream = stream.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no standard input was passed on"))?;
+    let mut data = Vec::new();
+    loop {
+        let bytes = stream
+            .read_bytes(64 * 1024, None::<&gio::Cancellable>)
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))?;
+        if bytes.is_empty() {
+            return Ok(data);
+        }
+        data.extend_from_slice(&bytes);
+    }
+}
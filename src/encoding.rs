@@ -0,0 +1,304 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+use encoding_rs::*;
+use std::io;
+
+/// How far into a file the UTF-16 and binary heuristics look.
+const SNIFF_LEN: usize = 4096;
+
+/// The character encoding of a document, and whether it carries a byte
+/// order mark that has to be written back on save.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TextEncoding {
+    encoding: &'static Encoding,
+    bom: bool,
+}
+
+/// Encodings offered in the encoding selector, in display order.
+pub fn choices() -> Vec<TextEncoding> {
+    let unicode = [
+        (UTF_8, false),
+        (UTF_8, true),
+        (UTF_16LE, true),
+        (UTF_16BE, true),
+        (UTF_16LE, false),
+        (UTF_16BE, false),
+    ];
+    let legacy = [
+        WINDOWS_1252,
+        ISO_8859_15,
+        ISO_8859_2,
+        WINDOWS_1250,
+        WINDOWS_1251,
+        KOI8_R,
+        ISO_8859_7,
+        WINDOWS_1254,
+        SHIFT_JIS,
+        EUC_JP,
+        GBK,
+        GB18030,
+        BIG5,
+        EUC_KR,
+    ];
+
+    unicode
+        .iter()
+        .map(|&(encoding, bom)| TextEncoding { encoding, bom })
+        .chain(legacy.iter().map(|&encoding| TextEncoding { encoding, bom: false }))
+        .collect()
+}
+
+impl TextEncoding {
+    pub fn utf8() -> TextEncoding {
+        TextEncoding {
+            encoding: UTF_8,
+            bom: false,
+        }
+    }
+
+    /// A stable identifier, used as the id of selector entries.
+    pub fn id(&self) -> String {
+        if self.bom {
+            format!("{}+BOM", self.encoding.name())
+        } else {
+            self.encoding.name().to_string()
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<TextEncoding> {
+        choices().into_iter().find(|choice| choice.id() == id)
+    }
+
+    /// Looks an encoding up by one of its standard labels, such as `utf-8`
+    /// or `latin1`, among those the selector offers.
+    pub fn from_label(label: &str) -> Option<TextEncoding> {
+        let encoding = Encoding::for_label(label.trim().as_bytes())?;
+        choices()
+            .into_iter()
+            .find(|choice| choice.encoding == encoding && !choice.bom)
+    }
+
+    pub fn label(&self) -> String {
+        if self.bom {
+            format!("{} with BOM", self.encoding.name())
+        } else {
+            self.encoding.name().to_string()
+        }
+    }
+
+    /// Decodes `bytes`, skipping the BOM if there is one. Returns `None` when
+    /// the bytes are not valid in this encoding.
+    pub fn decode(&self, bytes: &[u8]) -> Option<String> {
+        let bytes = match Encoding::for_bom(bytes) {
+            Some((encoding, length)) if encoding == self.encoding => &bytes[length..],
+            _ => bytes,
+        };
+        self.encoding
+            .decode_without_bom_handling_and_without_replacement(bytes)
+            .map(|text| text.into_owned())
+    }
+
+    /// Decodes `bytes` like `decode`, but replaces invalid sequences instead
+    /// of failing. Also returns whether anything had to be replaced.
+    pub fn decode_lossy(&self, bytes: &[u8]) -> (String, bool) {
+        let bytes = match Encoding::for_bom(bytes) {
+            Some((encoding, length)) if encoding == self.encoding => &bytes[length..],
+            _ => bytes,
+        };
+        let (text, had_errors) = self.encoding.decode_without_bom_handling(bytes);
+        (text.into_owned(), had_errors)
+    }
+
+    /// Encodes `text`, prefixed with the BOM if the document had one. Fails
+    /// when `text` contains characters the encoding cannot represent.
+    pub fn encode(&self, text: &str) -> io::Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(text.len() + 3);
+        if self.encoding == UTF_16LE {
+            if self.bom {
+                data.extend_from_slice(&[0xFF, 0xFE]);
+            }
+            for unit in text.encode_utf16() {
+                data.extend_from_slice(&unit.to_le_bytes());
+            }
+        } else if self.encoding == UTF_16BE {
+            if self.bom {
+                data.extend_from_slice(&[0xFE, 0xFF]);
+            }
+            for unit in text.encode_utf16() {
+                data.extend_from_slice(&unit.to_be_bytes());
+            }
+        } else {
+            if self.bom && self.encoding == UTF_8 {
+                data.extend_from_slice(&[0xEF, 0xBB, 0xBF]);
+            }
+            let (encoded, _, has_unmappable) = self.encoding.encode(text);
+            if has_unmappable {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("the text contains characters that cannot be saved as {}", self.encoding.name()),
+                ));
+            }
+            data.extend_from_slice(&encoded);
+        }
+        Ok(data)
+    }
+}
+
+/// Guesses the encoding of `bytes`: a BOM wins, then BOM-less UTF-16, then
+/// valid UTF-8, then Japanese encodings if they decode to text with kana
+/// in it, and finally Windows-1252, which accepts any input. The result
+/// always decodes `bytes` successfully.
+pub fn detect(bytes: &[u8]) -> TextEncoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        let detected = TextEncoding { encoding, bom: true };
+        if detected.decode(bytes).is_some() {
+            return detected;
+        }
+    }
+
+    // ASCII in UTF-16 is also valid UTF-8, full of NULs, so check it first.
+    if let Some(encoding) = guess_utf16(bytes) {
+        return TextEncoding { encoding, bom: false };
+    }
+
+    if std::str::from_utf8(bytes).is_ok() {
+        return TextEncoding::utf8();
+    }
+
+    for &encoding in &[SHIFT_JIS, EUC_JP] {
+        if let Some(text) = encoding.decode_without_bom_handling_and_without_replacement(bytes) {
+            if text.chars().any(|c| ('\u{3040}'..='\u{30FF}').contains(&c)) {
+                return TextEncoding { encoding, bom: false };
+            }
+        }
+    }
+
+    TextEncoding {
+        encoding: WINDOWS_1252,
+        bom: false,
+    }
+}
+
+/// Whether `bytes` look like binary data rather than text: the start of the
+/// file, unless it is UTF-16, holds a NUL byte or is more than a tenth
+/// control characters.
+pub fn looks_binary(bytes: &[u8]) -> bool {
+    if Encoding::for_bom(bytes).is_some() || guess_utf16(bytes).is_some() {
+        return false;
+    }
+
+    let sample = &bytes[..bytes.len().min(SNIFF_LEN)];
+    let controls = sample
+        .iter()
+        .filter(|&&b| (b < 0x20 && !b"\t\n\r\x0c\x1b".contains(&b)) || b == 0x7f)
+        .count();
+    sample.contains(&0) || controls * 10 > sample.len()
+}
+
+/// Mostly-ASCII UTF-16 has a zero byte in every other position; which half
+/// the zeros fall in gives away the byte order.
+fn guess_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(SNIFF_LEN) & !1];
+    if sample.is_empty() || bytes.len() % 2 != 0 {
+        return None;
+    }
+
+    let pairs = sample.len() / 2;
+    let even_zeros = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd_zeros = sample.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+
+    let encoding = if odd_zeros * 10 > pairs * 4 && even_zeros * 10 < pairs {
+        UTF_16LE
+    } else if even_zeros * 10 > pairs * 4 && odd_zeros * 10 < pairs {
+        UTF_16BE
+    } else {
+        return None;
+    };
+
+    encoding
+        .decode_without_bom_handling_and_without_replacement(bytes)
+        .map(|_| encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str, encoding: &'static Encoding) -> Vec<u8> {
+        TextEncoding { encoding, bom: false }.encode(text).unwrap()
+    }
+
+    #[test]
+    fn bom_wins() {
+        let utf8 = detect(b"\xEF\xBB\xBFcaf\xC3\xA9");
+        assert_eq!(utf8, TextEncoding { encoding: UTF_8, bom: true });
+        assert_eq!(utf8.decode(b"\xEF\xBB\xBFcaf\xC3\xA9").unwrap(), "café");
+
+        let le = detect(b"\xFF\xFEh\0i\0");
+        assert_eq!(le, TextEncoding { encoding: UTF_16LE, bom: true });
+        assert_eq!(le.decode(b"\xFF\xFEh\0i\0").unwrap(), "hi");
+        assert_eq!(le.encode("hi").unwrap(), b"\xFF\xFEh\0i\0");
+
+        assert_eq!(detect(b"\xFE\xFF\0h\0i"), TextEncoding { encoding: UTF_16BE, bom: true });
+    }
+
+    #[test]
+    fn utf16_without_bom() {
+        let le = utf16("line one\nline two\n", UTF_16LE);
+        assert_eq!(detect(&le), TextEncoding { encoding: UTF_16LE, bom: false });
+        let be = utf16("line one\nline two\n", UTF_16BE);
+        assert_eq!(detect(&be), TextEncoding { encoding: UTF_16BE, bom: false });
+
+        // An odd length cannot be UTF-16.
+        assert_eq!(guess_utf16(&le[..le.len() - 1]), None);
+        // Neither can text without zeros in it.
+        assert_eq!(guess_utf16(b"plain text"), None);
+        assert_eq!(guess_utf16(b""), None);
+    }
+
+    #[test]
+    fn utf8_and_legacy_fallback() {
+        assert_eq!(detect(b"plain"), TextEncoding::utf8());
+        assert_eq!(detect("café".as_bytes()), TextEncoding::utf8());
+        assert_eq!(detect(b""), TextEncoding::utf8());
+
+        let (kana, _, _) = SHIFT_JIS.encode("こんにちは");
+        assert_eq!(detect(&kana), TextEncoding { encoding: SHIFT_JIS, bom: false });
+
+        let latin1 = detect(b"caf\xE9");
+        assert_eq!(latin1, TextEncoding { encoding: WINDOWS_1252, bom: false });
+        assert_eq!(latin1.decode(b"caf\xE9").unwrap(), "café");
+    }
+
+    #[test]
+    fn binary_heuristic() {
+        assert!(!looks_binary(b"fn main() {\n\tprintln!();\r\n}\x0c\n"));
+        assert!(!looks_binary("naïve\n".as_bytes()));
+        assert!(!looks_binary(&utf16("text\n", UTF_16LE)));
+        assert!(!looks_binary(b"\xFF\xFEh\0i\0"));
+        assert!(!looks_binary(b""));
+
+        assert!(looks_binary(b"\x7FELF\x02\x01\x01\0\0"));
+        assert!(looks_binary(b"a\0b"));
+        // Control characters: one in ten is still text, more is not.
+        assert!(!looks_binary(b"\x01bcdefghij"));
+        assert!(looks_binary(b"\x01\x02cdefghij"));
+    }
+}
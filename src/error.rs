@@ -0,0 +1,104 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What went wrong with a file, in the terms the user is shown.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FileErrorKind {
+    NotFound,
+    PermissionDenied,
+    IsDirectory,
+    TooLarge,
+    InvalidEncoding,
+    Other,
+}
+
+/// A failure to read or write `path`, keeping the underlying OS error so it
+/// can be shown alongside the explanation.
+#[derive(Debug)]
+pub struct FileError {
+    kind: FileErrorKind,
+    path: PathBuf,
+    error: io::Error,
+}
+
+impl FileError {
+    pub fn new(path: &Path, error: io::Error) -> FileError {
+        let kind = match error.kind() {
+            io::ErrorKind::NotFound => FileErrorKind::NotFound,
+            io::ErrorKind::PermissionDenied => FileErrorKind::PermissionDenied,
+            io::ErrorKind::InvalidData => FileErrorKind::InvalidEncoding,
+            _ => match error.raw_os_error() {
+                Some(libc::EISDIR) => FileErrorKind::IsDirectory,
+                Some(libc::EFBIG) => FileErrorKind::TooLarge,
+                _ => FileErrorKind::Other,
+            },
+        };
+
+        FileError {
+            kind,
+            path: path.to_path_buf(),
+            error,
+        }
+    }
+
+    pub fn is_directory(path: &Path) -> FileError {
+        FileError::new(path, io::Error::from_raw_os_error(libc::EISDIR))
+    }
+
+    pub fn too_large(path: &Path, size: u64, limit: u64) -> FileError {
+        FileError {
+            kind: FileErrorKind::TooLarge,
+            path: path.to_path_buf(),
+            error: io::Error::new(
+                io::ErrorKind::Other,
+                format!("the file is {} bytes, more than the limit of {} bytes", size, limit),
+            ),
+        }
+    }
+
+    /// A one-line explanation for the user, without the OS error.
+    pub fn summary(&self) -> String {
+        let path = self.path.to_string_lossy();
+        match self.kind {
+            FileErrorKind::NotFound => format!("{} does not exist.", path),
+            FileErrorKind::PermissionDenied => format!("You do not have permission to access {}.", path),
+            FileErrorKind::IsDirectory => format!("{} is a folder, not a file.", path),
+            FileErrorKind::TooLarge => format!("{} is too large.", path),
+            FileErrorKind::InvalidEncoding => format!("{} does not match the chosen character encoding.", path),
+            FileErrorKind::Other => format!("Something went wrong with {}.", path),
+        }
+    }
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.summary(), self.error)
+    }
+}
+
+impl Error for FileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
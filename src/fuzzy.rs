@@ -0,0 +1,119 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+use std::path::Path;
+
+/// How well `query` matches `candidate`, ignoring case and spaces in the
+/// query: its characters have to appear in `candidate` in order, but not
+/// next to each other. Runs of adjacent characters and characters at the
+/// start of a word score higher. `None` when it does not match.
+pub fn score(query: &str, candidate: &str) -> Option<i32> {
+    let query: Vec<char> = query.chars().filter(|c| !c.is_whitespace()).collect();
+    let candidate: Vec<char> = candidate.chars().collect();
+    let first = match query.first() {
+        Some(&first) => first,
+        None => return Some(0),
+    };
+
+    // Matching greedily from the first fitting character can miss a better
+    // run further on, so every place the query could start is tried.
+    (0..candidate.len())
+        .filter(|&start| same_letter(candidate[start], first))
+        .filter_map(|start| score_from(&query, &candidate, start))
+        .max()
+}
+
+/// Scores `path` like `score` does, except that a match in the file name
+/// counts for more than one spread over the folders.
+pub fn path_score(query: &str, path: &Path) -> Option<i32> {
+    let full = score(query, &path.to_string_lossy())?;
+    let name = path.file_name().and_then(|name| score(query, &name.to_string_lossy()));
+    Some(full.max(name.map_or(0, |score| score * 2)))
+}
+
+fn score_from(query: &[char], candidate: &[char], start: usize) -> Option<i32> {
+    let mut score = 0;
+    let mut next = start;
+    let mut previous = None;
+
+    for &wanted in query {
+        let found = (next..candidate.len()).find(|&index| same_letter(candidate[index], wanted))?;
+        score += 1;
+        if found > 0 && previous == Some(found - 1) {
+            score += 5;
+        }
+        if found == 0 || !candidate[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(found);
+        next = found + 1;
+    }
+
+    Some(score)
+}
+
+fn same_letter(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_match() {
+        assert_eq!(score("xyz", "main.rs"), None);
+        assert_eq!(score("sr", "rs"), None);
+        assert_eq!(path_score("zz", Path::new("/src/main.rs")), None);
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        assert_eq!(score("", "main.rs"), Some(0));
+        assert_eq!(score(" ", ""), Some(0));
+    }
+
+    #[test]
+    fn ignores_case_and_spaces() {
+        assert_eq!(score("MAIN", "main.rs"), score("main", "main.rs"));
+        assert_eq!(score("ma in", "main.rs"), score("main", "main.rs"));
+    }
+
+    #[test]
+    fn prefix_beats_subsequence() {
+        assert!(score("main", "main.rs") > score("main", "my_animal_index.rs"));
+        assert!(score("tab", "tabs.rs") > score("tab", "state_bar.rs"));
+    }
+
+    #[test]
+    fn finds_the_best_run() {
+        // Starting at the first "s" would spread the match out.
+        assert!(score("set", "src/settings.rs") > score("set", "s/e/t.rs"));
+    }
+
+    #[test]
+    fn basename_beats_full_path() {
+        let in_name = path_score("recent", Path::new("/home/me/src/recent.rs"));
+        let in_folders = path_score("recent", Path::new("/home/me/recent/main.rs"));
+        assert!(in_name > in_folders);
+        assert!(in_name > score("recent", "/home/me/src/recent.rs"));
+        // Matches that need the folders still count.
+        assert!(path_score("srcmain", Path::new("/src/main.rs")).is_some());
+    }
+}
@@ -0,0 +1,241 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The patterns of one `.gitignore` file. They apply to the directory the
+/// file is in and everything below it.
+pub struct Gitignore {
+    root: PathBuf,
+    rules: Vec<Rule>,
+}
+
+struct Rule {
+    pattern: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl Gitignore {
+    /// Reads the `.gitignore` in `dir`, if there is one.
+    pub fn from_dir(dir: &Path) -> Option<Gitignore> {
+        let text = fs::read_to_string(dir.join(".gitignore")).ok()?;
+        Some(Gitignore {
+            root: dir.to_path_buf(),
+            rules: text.lines().filter_map(Rule::parse).collect(),
+        })
+    }
+
+    /// Whether `path` is ignored (`Some(true)`), re-included by a `!`
+    /// pattern (`Some(false)`), or not mentioned at all. As in git, the last
+    /// matching pattern wins.
+    pub fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(&self.root).ok()?.to_string_lossy();
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.pattern.is_match(&relative))
+            .map(|rule| !rule.negated)
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Rule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        // A slash anywhere but at the end ties the pattern to the directory
+        // of the .gitignore; otherwise it matches at any depth.
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+
+        let pattern = if anchored {
+            format!("^{}$", glob_to_regex(line))
+        } else {
+            format!("^(?:.*/)?{}$", glob_to_regex(line))
+        };
+        Regex::new(&pattern).ok().map(|pattern| Rule {
+            pattern,
+            negated,
+            dir_only,
+        })
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::with_capacity(glob.len() * 2);
+    let mut rest = glob;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("**/") {
+            regex.push_str("(?:.*/)?");
+            rest = &rest[3..];
+            continue;
+        }
+        if rest == "**" {
+            regex.push_str(".*");
+            break;
+        }
+
+        rest = &rest[c.len_utf8()..];
+        match c {
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => match rest.find(']') {
+                Some(close) => {
+                    let class = &rest[..close];
+                    regex.push('[');
+                    let class = match class.strip_prefix('!') {
+                        Some(class) => {
+                            regex.push('^');
+                            class
+                        }
+                        None => class,
+                    };
+                    regex.push_str(&class.replace('\\', "\\\\").replace('[', "\\["));
+                    regex.push(']');
+                    rest = &rest[close + 1..];
+                }
+                None => regex.push_str("\\["),
+            },
+            '\\' => {
+                if let Some(escaped) = rest.chars().next() {
+                    regex.push_str(&regex::escape(&escaped.to_string()));
+                    rest = &rest[escaped.len_utf8()..];
+                }
+            }
+            _ => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignore(text: &str) -> Gitignore {
+        Gitignore {
+            root: PathBuf::from("/repo"),
+            rules: text.lines().filter_map(Rule::parse).collect(),
+        }
+    }
+
+    fn file(ignore: &Gitignore, path: &str) -> Option<bool> {
+        ignore.matched(&Path::new("/repo").join(path), false)
+    }
+
+    fn dir(ignore: &Gitignore, path: &str) -> Option<bool> {
+        ignore.matched(&Path::new("/repo").join(path), true)
+    }
+
+    #[test]
+    fn unanchored_patterns_match_at_any_depth() {
+        let ignore = ignore("*.log");
+        assert_eq!(file(&ignore, "a.log"), Some(true));
+        assert_eq!(file(&ignore, "deep/down/b.log"), Some(true));
+        assert_eq!(file(&ignore, "a.log.txt"), None);
+        assert_eq!(file(&ignore, "a.txt"), None);
+    }
+
+    #[test]
+    fn leading_and_inner_slashes_anchor() {
+        let ignore = ignore("/build\ndocs/out");
+        assert_eq!(dir(&ignore, "build"), Some(true));
+        assert_eq!(dir(&ignore, "sub/build"), None);
+        assert_eq!(dir(&ignore, "docs/out"), Some(true));
+        assert_eq!(dir(&ignore, "sub/docs/out"), None);
+    }
+
+    #[test]
+    fn trailing_slash_only_matches_directories() {
+        let ignore = ignore("target/");
+        assert_eq!(dir(&ignore, "target"), Some(true));
+        assert_eq!(dir(&ignore, "sub/target"), Some(true));
+        assert_eq!(file(&ignore, "target"), None);
+    }
+
+    #[test]
+    fn double_stars() {
+        let ignore = ignore("**/cache\nlogs/**\na/**/b");
+        assert_eq!(dir(&ignore, "cache"), Some(true));
+        assert_eq!(dir(&ignore, "x/y/cache"), Some(true));
+        assert_eq!(file(&ignore, "logs/2020/today.txt"), Some(true));
+        assert_eq!(file(&ignore, "a/b"), Some(true));
+        assert_eq!(file(&ignore, "a/x/y/b"), Some(true));
+        assert_eq!(file(&ignore, "a/xb"), None);
+    }
+
+    #[test]
+    fn single_characters_and_classes() {
+        let ignore = ignore("?.o\n[ab].c\n[!x].h");
+        assert_eq!(file(&ignore, "a.o"), Some(true));
+        assert_eq!(file(&ignore, "ab.o"), None);
+        assert_eq!(file(&ignore, "b.c"), Some(true));
+        assert_eq!(file(&ignore, "c.c"), None);
+        assert_eq!(file(&ignore, "y.h"), Some(true));
+        assert_eq!(file(&ignore, "x.h"), None);
+    }
+
+    #[test]
+    fn negation_and_last_match_wins() {
+        let ignore = ignore("*.log\n!keep.log");
+        assert_eq!(file(&ignore, "other.log"), Some(true));
+        assert_eq!(file(&ignore, "keep.log"), Some(false));
+    }
+
+    #[test]
+    fn escapes_and_literals() {
+        let ignore = ignore("\\#hash\n\\!bang\nfile.txt\na+b\nstar\\*");
+        assert_eq!(file(&ignore, "#hash"), Some(true));
+        assert_eq!(file(&ignore, "!bang"), Some(true));
+        assert_eq!(file(&ignore, "file.txt"), Some(true));
+        assert_eq!(file(&ignore, "fileXtxt"), None);
+        assert_eq!(file(&ignore, "a+b"), Some(true));
+        assert_eq!(file(&ignore, "star*"), Some(true));
+        assert_eq!(file(&ignore, "stars"), None);
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let ignore = ignore("# a comment\n\n   \n/");
+        assert!(ignore.rules.is_empty());
+        assert_eq!(file(&ignore, "# a comment"), None);
+    }
+
+    #[test]
+    fn paths_outside_the_root_are_not_matched() {
+        let ignore = ignore("*");
+        assert_eq!(ignore.matched(Path::new("/elsewhere/a"), false), None);
+    }
+}
@@ -0,0 +1,134 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+/// A position to jump to. Both numbers count from 1.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Target {
+    pub line: i32,
+    pub column: Option<i32>,
+}
+
+/// Reads what was typed into the go-to-line box: a line (`42`), a line and
+/// column (`42:7`), an offset from `current` (`+10`, `-5`, also with a
+/// column) or a percentage of the document (`50%`). Fails with a message to
+/// show next to the box when the input does not parse or the line is not
+/// between 1 and `line_count`.
+pub fn parse(input: &str, current: i32, line_count: i32) -> Result<Target, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(String::new());
+    }
+
+    if let Some(percent) = input.strip_suffix('%') {
+        let percent: f64 = percent.trim().parse().map_err(|_| "Not a valid percentage".to_string())?;
+        if !(0.0..=100.0).contains(&percent) {
+            return Err("Use a percentage from 0 to 100".to_string());
+        }
+        let line = ((percent / 100.0) * line_count as f64).round() as i32;
+        return Ok(Target {
+            line: line.max(1).min(line_count),
+            column: None,
+        });
+    }
+
+    let (line, column) = match input.find(':') {
+        Some(colon) => (&input[..colon], Some(&input[colon + 1..])),
+        None => (input, None),
+    };
+
+    let line = line.trim();
+    let number = |text: &str| text.parse::<i32>().map_err(|_| format!("“{}” is not a number", text));
+    let line = if let Some(offset) = line.strip_prefix('+') {
+        current.saturating_add(number(offset)?)
+    } else if let Some(offset) = line.strip_prefix('-') {
+        current.saturating_sub(number(offset)?)
+    } else {
+        number(line)?
+    };
+    if line < 1 || line > line_count {
+        return Err(format!("Lines go from 1 to {}", line_count));
+    }
+
+    let column = match column.map(str::trim) {
+        Some(column) => {
+            let column = number(column)?;
+            if column < 1 {
+                return Err("Columns start at 1".to_string());
+            }
+            Some(column)
+        }
+        None => None,
+    };
+
+    Ok(Target { line, column })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(line: i32, column: Option<i32>) -> Result<Target, String> {
+        Ok(Target { line, column })
+    }
+
+    #[test]
+    fn line() {
+        assert_eq!(parse("42", 1, 100), target(42, None));
+        assert_eq!(parse("  7 ", 1, 100), target(7, None));
+    }
+
+    #[test]
+    fn line_and_column() {
+        assert_eq!(parse("42:7", 1, 100), target(42, Some(7)));
+        assert_eq!(parse("+2:3", 10, 100), target(12, Some(3)));
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(parse("+10", 5, 100), target(15, None));
+        assert_eq!(parse("-4", 5, 100), target(1, None));
+    }
+
+    #[test]
+    fn percentage() {
+        assert_eq!(parse("50%", 1, 200), target(100, None));
+        assert_eq!(parse("0%", 1, 200), target(1, None));
+        assert_eq!(parse("100%", 1, 200), target(200, None));
+        assert!(parse("150%", 1, 200).is_err());
+        assert!(parse("half%", 1, 200).is_err());
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_eq!(parse("0", 1, 100), Err("Lines go from 1 to 100".to_string()));
+        assert!(parse("101", 1, 100).is_err());
+        assert!(parse("+10", 95, 100).is_err());
+        assert!(parse("-5", 5, 100).is_err());
+        assert_eq!(parse("3:0", 1, 100), Err("Columns start at 1".to_string()));
+    }
+
+    #[test]
+    fn garbage() {
+        assert_eq!(parse("", 1, 100), Err(String::new()));
+        assert_eq!(parse("abc", 1, 100), Err("“abc” is not a number".to_string()));
+        assert!(parse("4:x", 1, 100).is_err());
+        assert!(parse("+", 1, 100).is_err());
+        assert!(parse("99999999999", 1, 100).is_err());
+    }
+}
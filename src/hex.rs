@@ -0,0 +1,102 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+use std::fmt::Write;
+
+pub const BYTES_PER_LINE: usize = 16;
+
+/// Only the start of a binary file is dumped; the dump is about four times
+/// the size of the bytes it shows.
+pub const VIEW_LIMIT: usize = 1 << 20;
+
+/// Formats `bytes` the way `hexdump -C` does: an offset column, sixteen bytes
+/// in hex split into two groups, and a gutter with the printable ASCII
+/// characters, dots standing in for the rest.
+pub fn dump(bytes: &[u8]) -> String {
+    let lines = (bytes.len() + BYTES_PER_LINE - 1) / BYTES_PER_LINE;
+    let mut dump = String::with_capacity(lines * 80);
+
+    for (line, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let _ = write!(dump, "{:08x} ", line * BYTES_PER_LINE);
+        for column in 0..BYTES_PER_LINE {
+            if column % 8 == 0 {
+                dump.push(' ');
+            }
+            match chunk.get(column) {
+                Some(byte) => {
+                    let _ = write!(dump, "{:02x} ", byte);
+                }
+                None => dump.push_str("   "),
+            }
+        }
+
+        dump.push_str(" |");
+        dump.extend(chunk.iter().map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            }
+        }));
+        dump.push_str("|\n");
+    }
+
+    dump
+}
+
+/// Dumps at most the first `VIEW_LIMIT` bytes of `bytes`, and tells whether
+/// that left any out.
+pub fn dump_start(bytes: &[u8]) -> (String, bool) {
+    (dump(&bytes[..bytes.len().min(VIEW_LIMIT)]), bytes.len() > VIEW_LIMIT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_and_partial_lines() {
+        let expected = concat!(
+            "00000000  30 31 32 33 34 35 36 37  38 39 61 62 63 64 65 66  |0123456789abcdef|\n",
+            "00000010  58 59 01 20 7f                                    |XY. .|\n",
+        );
+        assert_eq!(dump(b"0123456789abcdefXY\x01 \x7f"), expected);
+    }
+
+    #[test]
+    fn columns_line_up() {
+        for length in 1..=BYTES_PER_LINE {
+            let dump = dump(&vec![b'a'; length]);
+            assert_eq!(dump.find('|'), Some(60), "{} bytes", length);
+        }
+        assert_eq!(dump(b""), "");
+    }
+
+    #[test]
+    fn stops_at_the_view_limit() {
+        let (dump, cut) = dump_start(&vec![0; VIEW_LIMIT]);
+        assert!(!cut);
+        assert_eq!(dump.lines().count(), VIEW_LIMIT / BYTES_PER_LINE);
+
+        let (dump, cut) = dump_start(&vec![0; VIEW_LIMIT + 1]);
+        assert!(cut);
+        assert_eq!(dump.lines().count(), VIEW_LIMIT / BYTES_PER_LINE);
+        assert!(dump.ends_with("|................|\n"));
+    }
+}
@@ -0,0 +1,189 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+/// The newline style a document is written with.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+pub const CHOICES: [LineEnding; 3] = [LineEnding::Lf, LineEnding::CrLf, LineEnding::Cr];
+
+impl LineEnding {
+    pub fn id(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+            LineEnding::Cr => "CR",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<LineEnding> {
+        CHOICES.iter().find(|choice| choice.id() == id).cloned()
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "Unix (LF)",
+            LineEnding::CrLf => "Windows (CRLF)",
+            LineEnding::Cr => "Classic Mac (CR)",
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    /// Rewrites every line break in `text`, whatever its style, as this one.
+    pub fn apply(&self, text: &str) -> String {
+        let mut converted = String::with_capacity(text.len());
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\r' => {
+                    if chars.peek() == Some(&'\n') {
+                        chars.next();
+                    }
+                    converted.push_str(self.as_str());
+                }
+                '\n' => converted.push_str(self.as_str()),
+                _ => converted.push(c),
+            }
+        }
+        converted
+    }
+}
+
+/// The lines of `text`, split at every line break whatever its style: the
+/// breaks `detect` counts. As with `str::lines`, a break at the very end does
+/// not start another line.
+pub fn lines(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let line = match rest.find(&['\r', '\n'][..]) {
+            Some(end) => {
+                let line = &rest[..end];
+                let after = if rest[end..].starts_with("\r\n") { end + 2 } else { end + 1 };
+                rest = &rest[after..];
+                line
+            }
+            None => std::mem::take(&mut rest),
+        };
+        Some(line)
+    })
+}
+
+/// Finds the most common line ending in `text`, and whether more than one
+/// style is present. Text without any line break counts as LF.
+pub fn detect(text: &str) -> (LineEnding, bool) {
+    let (mut lf, mut crlf, mut cr) = (0, 0, 0);
+    let mut bytes = text.bytes().peekable();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'\r' => {
+                if bytes.peek() == Some(&b'\n') {
+                    bytes.next();
+                    crlf += 1;
+                } else {
+                    cr += 1;
+                }
+            }
+            b'\n' => lf += 1,
+            _ => (),
+        }
+    }
+
+    let dominant = if crlf > lf && crlf >= cr {
+        LineEnding::CrLf
+    } else if cr > lf && cr > crlf {
+        LineEnding::Cr
+    } else {
+        LineEnding::Lf
+    };
+    let styles = [lf, crlf, cr].iter().filter(|&&count| count > 0).count();
+
+    (dominant, styles > 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_each_style() {
+        assert_eq!(detect("a\nb\n"), (LineEnding::Lf, false));
+        assert_eq!(detect("a\r\nb\r\n"), (LineEnding::CrLf, false));
+        assert_eq!(detect("a\rb\r"), (LineEnding::Cr, false));
+        assert_eq!(detect("no break"), (LineEnding::Lf, false));
+        assert_eq!(detect(""), (LineEnding::Lf, false));
+    }
+
+    #[test]
+    fn mixed_endings() {
+        assert_eq!(detect("a\r\nb\r\nc\n"), (LineEnding::CrLf, true));
+        assert_eq!(detect("a\nb\nc\r\n"), (LineEnding::Lf, true));
+        assert_eq!(detect("a\r\rb\n"), (LineEnding::Cr, true));
+        // A tie goes to LF.
+        assert_eq!(detect("a\r\nb\n"), (LineEnding::Lf, true));
+        // A CR right before a CRLF is a break of its own; CRLF wins the tie.
+        assert_eq!(detect("a\r\r\n"), (LineEnding::CrLf, true));
+    }
+
+    #[test]
+    fn crlf_at_a_chunk_boundary() {
+        // Files are read and inserted a megabyte at a time; the pair must
+        // still count as one break when it straddles that boundary.
+        let text = format!("{}\r\nb\r\n", "a".repeat((1 << 20) - 1));
+        assert_eq!(detect(&text), (LineEnding::CrLf, false));
+        let converted = LineEnding::Lf.apply(&text);
+        assert_eq!(converted.len(), text.len() - 2);
+        assert!(!converted.contains('\r'));
+    }
+
+    #[test]
+    fn apply_rewrites_every_break() {
+        let mixed = "a\r\nb\rc\nd\r";
+        assert_eq!(LineEnding::Lf.apply(mixed), "a\nb\nc\nd\n");
+        assert_eq!(LineEnding::CrLf.apply(mixed), "a\r\nb\r\nc\r\nd\r\n");
+        assert_eq!(LineEnding::Cr.apply(mixed), "a\rb\rc\rd\r");
+        assert_eq!(LineEnding::CrLf.apply("a\r\n"), "a\r\n");
+        assert_eq!(LineEnding::Lf.apply("\r\r\n"), "\n\n");
+    }
+
+    #[test]
+    fn lines_split_at_any_break() {
+        let split = |text| lines(text).collect::<Vec<_>>();
+        assert_eq!(split("a\r\nb\rc\nd"), ["a", "b", "c", "d"]);
+        assert_eq!(split("a\r"), ["a"]);
+        assert_eq!(split("a\r\r\nb"), ["a", "", "b"]);
+        assert_eq!(split("a\n\n"), ["a", ""]);
+        assert_eq!(split("\r\n"), [""]);
+        assert!(split("").is_empty());
+        assert_eq!(split("x\ny\n\nz\n"), "x\ny\n\nz\n".lines().collect::<Vec<_>>());
+    }
+}
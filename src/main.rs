@@ -17,11 +17,52 @@
  * Authored by: mazen <https://github.com/maze-n>
  */
 
+pub mod atomic;
+pub mod cli;
 pub mod components;
+pub mod encoding;
+pub mod error;
+pub mod fuzzy;
+pub mod gitignore;
+pub mod goto;
+pub mod hex;
+pub mod line_ending;
+pub mod recovery;
+pub mod search;
+pub mod session;
 pub mod state;
 
-use components::App;
+use cli::Command;
+use components::Windows;
+use std::env;
+use std::process;
 
 fn main() {
-    App::new().connect_events().execute();
+    // Help, the version and mistakes are answered here, before any running
+    // instance gets the arguments.
+    let cwd = env::current_dir().unwrap_or_default();
+    match cli::parse(env::args().skip(1), &cwd) {
+        Ok(Command::Run(_)) => (),
+        Ok(Command::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Ok(Command::Version) => {
+            println!("eddit {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(error) => {
+            eprintln!("eddit: {}\nTry --help for more information.", error);
+            process::exit(2);
+        }
+    }
+
+    let windows = match Windows::new() {
+        Ok(windows) => windows,
+        Err(_) => {
+            eprintln!("Failed to initialize GTK");
+            process::exit(1);
+        }
+    };
+    process::exit(windows.run());
 }
@@ -0,0 +1,319 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+use crate::atomic;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::PathBuf;
+use std::process;
+
+const HEADER: &str = "eddit-recovery 2\n";
+const EXTENSION: &str = "snapshot";
+
+/// Past this many line pairs the diff is skipped; a recovery prompt is not
+/// worth a multi-second stall.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// The unsaved text of one document, as kept in the recovery journal.
+pub struct Snapshot {
+    pub path: Option<PathBuf>,
+    pub text: String,
+}
+
+/// A snapshot left behind by an eddit process that is no longer running.
+pub struct Orphan {
+    pub file: PathBuf,
+    pub snapshot: Snapshot,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DiffLine {
+    Same(String),
+    Removed(String),
+    Added(String),
+}
+
+/// `$XDG_STATE_HOME/eddit`, falling back to `~/.local/state/eddit`.
+pub fn journal_dir() -> PathBuf {
+    match env::var_os("XDG_STATE_HOME") {
+        Some(ref dir) if !dir.is_empty() => PathBuf::from(dir).join("eddit"),
+        _ => {
+            let home = env::var_os("HOME").unwrap_or_default();
+            PathBuf::from(home).join(".local/state/eddit")
+        }
+    }
+}
+
+/// Creates the journal folder, if need be, where only we may look: it holds
+/// unsaved text.
+pub fn create_journal_dir() -> io::Result<PathBuf> {
+    let dir = journal_dir();
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+    if fs::metadata(&dir)?.permissions().mode() & 0o077 != 0 {
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(dir)
+}
+
+/// The journal file for document `id` of this process.
+fn snapshot_file(id: usize) -> PathBuf {
+    journal_dir().join(format!("{}-{}.{}", process::id(), id, EXTENSION))
+}
+
+pub fn save(id: usize, snapshot: &Snapshot) -> io::Result<()> {
+    create_journal_dir()?;
+    atomic::write_private(&snapshot_file(id), &encode(snapshot)).map(|_| ())
+}
+
+/// The saved form of `snapshot`: the length of the path, empty for a new
+/// document, on a line of its own, then the path, which may hold line
+/// breaks, and a line break, and then the text.
+fn encode(snapshot: &Snapshot) -> Vec<u8> {
+    let path = snapshot.path.as_ref().map_or(&[][..], |path| path.as_os_str().as_bytes());
+    let mut data = HEADER.as_bytes().to_vec();
+    data.extend_from_slice(format!("{}\n", path.len()).as_bytes());
+    data.extend_from_slice(path);
+    data.push(b'\n');
+    data.extend_from_slice(snapshot.text.as_bytes());
+    data
+}
+
+pub fn remove(id: usize) {
+    let _ = fs::remove_file(snapshot_file(id));
+}
+
+pub fn remove_orphan(orphan: &Orphan) {
+    let _ = fs::remove_file(&orphan.file);
+}
+
+fn parse(data: &[u8]) -> Option<Snapshot> {
+    let data = data.strip_prefix(HEADER.as_bytes())?;
+    let end = data.iter().position(|&b| b == b'\n')?;
+    let length: usize = std::str::from_utf8(&data[..end]).ok()?.parse().ok()?;
+    let data = &data[end + 1..];
+    if data.get(length) != Some(&b'\n') {
+        return None;
+    }
+    let path = match &data[..length] {
+        [] => None,
+        path => Some(PathBuf::from(OsStr::from_bytes(path))),
+    };
+    let text = String::from_utf8(data[length + 1..].to_vec()).ok()?;
+    Some(Snapshot { path, text })
+}
+
+/// Snapshots whose owning process has exited, i.e. that crashed or was
+/// killed before the documents were saved or closed.
+pub fn orphans() -> Vec<Orphan> {
+    let entries = match fs::read_dir(journal_dir()) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut orphans: Vec<Orphan> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|file| file.extension() == Some(OsStr::new(EXTENSION)))
+        .filter(|file| {
+            let pid = file
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.split('-').next())
+                .and_then(|pid| pid.parse::<libc::pid_t>().ok());
+            match pid {
+                Some(pid) => !is_running(pid),
+                None => false,
+            }
+        })
+        .filter_map(|file| {
+            let snapshot = parse(&fs::read(&file).ok()?)?;
+            Some(Orphan { file, snapshot })
+        })
+        .collect();
+    orphans.sort_by(|a, b| a.file.cmp(&b.file));
+    orphans
+}
+
+fn is_running(pid: libc::pid_t) -> bool {
+    if pid as u32 == process::id() {
+        return true;
+    }
+    // Signal 0 only checks that the process exists; EPERM means it does but
+    // belongs to someone else.
+    let exists = unsafe { libc::kill(pid, 0) } == 0;
+    exists || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// A line diff from `old` to `new`, built from their longest common
+/// subsequence. Returns `None` when the inputs are too large to compare.
+pub fn diff(old: &str, new: &str) -> Option<Vec<DiffLine>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    if old_middle.len().saturating_mul(new_middle.len()) > MAX_DIFF_CELLS {
+        return None;
+    }
+
+    // lengths[i][j] is the LCS length of old_middle[i..] and new_middle[j..].
+    let width = new_middle.len() + 1;
+    let mut lengths = vec![0u32; (old_middle.len() + 1) * width];
+    for i in (0..old_middle.len()).rev() {
+        for j in (0..new_middle.len()).rev() {
+            lengths[i * width + j] = if old_middle[i] == new_middle[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut lines: Vec<DiffLine> = old[..prefix].iter().map(|line| DiffLine::Same(line.to_string())).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < old_middle.len() || j < new_middle.len() {
+        if i < old_middle.len() && j < new_middle.len() && old_middle[i] == new_middle[j] {
+            lines.push(DiffLine::Same(old_middle[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if i < old_middle.len()
+            && (j == new_middle.len() || lengths[(i + 1) * width + j] >= lengths[i * width + j + 1])
+        {
+            lines.push(DiffLine::Removed(old_middle[i].to_string()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(new_middle[j].to_string()));
+            j += 1;
+        }
+    }
+    lines.extend(old[old.len() - suffix..].iter().map(|line| DiffLine::Same(line.to_string())));
+
+    Some(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn snapshot(path: Option<&str>, text: &str) -> Snapshot {
+        Snapshot {
+            path: path.map(PathBuf::from),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for &(path, text) in [(Some("/tmp/odd\nname.txt"), "a\nb\n"), (None, ""), (Some("/x"), "\n\n")].iter() {
+            let parsed = parse(&encode(&snapshot(path, text))).unwrap();
+            assert_eq!(parsed.path, path.map(PathBuf::from));
+            assert_eq!(parsed.text, text);
+        }
+    }
+
+    #[test]
+    fn damaged_snapshots_are_refused() {
+        let data = encode(&snapshot(Some("/tmp/file.txt"), "text"));
+        assert!(parse(&data[..HEADER.len() + 6]).is_none());
+        assert!(parse(b"eddit-recovery 2\n20\n/short\ntext").is_none());
+        assert!(parse(b"eddit-recovery 1\n/tmp/file.txt\ntext").is_none());
+        assert!(parse(b"").is_none());
+    }
+
+    fn same(line: &str) -> DiffLine {
+        DiffLine::Same(line.to_string())
+    }
+
+    fn removed(line: &str) -> DiffLine {
+        DiffLine::Removed(line.to_string())
+    }
+
+    fn added(line: &str) -> DiffLine {
+        DiffLine::Added(line.to_string())
+    }
+
+    #[test]
+    fn diff_of_equal_texts() {
+        assert_eq!(diff("a\nb", "a\nb"), Some(vec![same("a"), same("b")]));
+        assert_eq!(diff("", ""), Some(vec![]));
+    }
+
+    #[test]
+    fn diff_keeps_common_lines() {
+        assert_eq!(
+            diff("a\nb\nc\nd", "a\nx\nc\nd\ne"),
+            Some(vec![same("a"), removed("b"), added("x"), same("c"), same("d"), added("e")])
+        );
+        assert_eq!(
+            diff("one\ntwo\nthree", "two\nthree\nfour"),
+            Some(vec![removed("one"), same("two"), same("three"), added("four")])
+        );
+    }
+
+    #[test]
+    fn diff_gives_up_on_large_changes() {
+        let old: String = (0..2001).map(|line| format!("old {}\n", line)).collect();
+        let new: String = (0..2001).map(|line| format!("new {}\n", line)).collect();
+        assert!(diff(&old, &new).is_none());
+
+        // Only the lines that differ count towards the limit.
+        let old = format!("{}changed\n{}", new, new);
+        let new = format!("{}other\n{}", new, new);
+        assert!(diff(&old, &new).is_some());
+    }
+
+    #[test]
+    fn orphans_of_exited_processes() {
+        let state = std::env::temp_dir().join(format!("eddit-recovery-test-{}", process::id()));
+        let _ = fs::remove_dir_all(&state);
+        env::set_var("XDG_STATE_HOME", &state);
+        let dir = create_journal_dir().unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+
+        let mut child = Command::new("true").spawn().unwrap();
+        let exited = child.id();
+        child.wait().unwrap();
+
+        let data = encode(&snapshot(Some("/tmp/file.txt"), "unsaved"));
+        fs::write(dir.join(format!("{}-0.{}", exited, EXTENSION)), &data).unwrap();
+        fs::write(dir.join(format!("{}-1.{}", process::id(), EXTENSION)), &data).unwrap();
+        fs::write(dir.join(format!("{}-2.{}", exited, EXTENSION)), b"garbage").unwrap();
+        fs::write(dir.join(format!("{}-3.txt", exited)), &data).unwrap();
+
+        let orphans = orphans();
+        fs::remove_dir_all(&state).unwrap();
+
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].file, dir.join(format!("{}-0.{}", exited, EXTENSION)));
+        assert_eq!(orphans[0].snapshot.text, "unsaved");
+    }
+}
@@ -0,0 +1,507 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+use crate::atomic::{self, Fallback, Written};
+use crate::encoding::{self, looks_binary};
+use crate::gitignore::Gitignore;
+use crate::line_ending;
+use regex::{Captures, Regex, RegexBuilder};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Files bigger than this are left out of a search.
+const MAX_SEARCH_SIZE: u64 = 16 * 1024 * 1024;
+
+/// What to look for, with the same options as the search bar.
+pub struct Query {
+    pub text: String,
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub regex: bool,
+}
+
+/// A line of a file with at least one match on it.
+pub struct Hit {
+    /// The line number, counting from 1.
+    pub line: u32,
+    /// The character offset of the first match in the line.
+    pub column: u32,
+    pub text: String,
+}
+
+/// The matches in one file.
+pub struct FileHits {
+    pub path: PathBuf,
+    pub hits: Vec<Hit>,
+}
+
+impl Query {
+    /// The regular expression for the query. Without the regex option the
+    /// text is matched literally.
+    pub fn compile(&self) -> Result<Regex, regex::Error> {
+        let pattern = if self.regex {
+            self.text.clone()
+        } else {
+            regex::escape(&self.text)
+        };
+        let pattern = if self.whole_word {
+            format!(r"\b(?:{})\b", pattern)
+        } else {
+            pattern
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .multi_line(true)
+            .build()
+    }
+}
+
+/// Searches every text file under `root`, skipping `.git`, whatever the
+/// `.gitignore` files along the way exclude, and binary files. `found` is
+/// called for each file with matches, in path order, until `canceled` is set.
+pub fn search_tree<F: FnMut(FileHits)>(root: &Path, regex: &Regex, canceled: &AtomicBool, found: &mut F) {
+    walk(root, regex, canceled, &mut Vec::new(), found);
+}
+
+fn walk<F: FnMut(FileHits)>(
+    dir: &Path,
+    regex: &Regex,
+    canceled: &AtomicBool,
+    ignores: &mut Vec<Gitignore>,
+    found: &mut F,
+) {
+    let mut entries: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(Result::ok).collect(),
+        Err(_) => return,
+    };
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let ignore = Gitignore::from_dir(dir);
+    let has_ignore = ignore.is_some();
+    ignores.extend(ignore);
+
+    for entry in entries {
+        if canceled.load(Ordering::SeqCst) {
+            break;
+        }
+
+        // Symbolic links are not followed, so a link cannot loop the walk.
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(_) => continue,
+        };
+        let path = entry.path();
+        if entry.file_name() == ".git" || is_ignored(ignores, &path, file_type.is_dir()) {
+            continue;
+        }
+
+        if file_type.is_dir() {
+            walk(&path, regex, canceled, ignores, found);
+        } else if file_type.is_file() {
+            if let Some(hits) = search_file(&path, regex) {
+                found(hits);
+            }
+        }
+    }
+
+    if has_ignore {
+        ignores.pop();
+    }
+}
+
+/// The deepest `.gitignore` with an opinion about `path` decides.
+fn is_ignored(ignores: &[Gitignore], path: &Path, is_dir: bool) -> bool {
+    ignores
+        .iter()
+        .rev()
+        .find_map(|ignore| ignore.matched(path, is_dir))
+        .unwrap_or(false)
+}
+
+/// The lines of the file at `path` that `regex` matches. `None` when there
+/// are none, or the file is too big, unreadable or binary.
+fn search_file(path: &Path, regex: &Regex) -> Option<FileHits> {
+    if fs::metadata(path).ok()?.len() > MAX_SEARCH_SIZE {
+        return None;
+    }
+    let data = fs::read(path).ok()?;
+    if looks_binary(&data) {
+        return None;
+    }
+    let text = encoding::detect(&data).decode(&data)?;
+
+    let hits: Vec<Hit> = line_ending::lines(&text)
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let first = regex.find(line)?;
+            Some(Hit {
+                line: index as u32 + 1,
+                column: line[..first.start()].chars().count() as u32,
+                text: line.to_string(),
+            })
+        })
+        .collect();
+
+    if hits.is_empty() {
+        None
+    } else {
+        Some(FileHits {
+            path: path.to_path_buf(),
+            hits,
+        })
+    }
+}
+
+/// What a match is replaced with, in the syntax of the search bar: groups
+/// as `\1`, `\g<1>`, `\g<name>`, `$1`, `${1}` or `${name}`, `$$` for a
+/// dollar sign, `\U`, `\L` and `\E` to change case up to the next one,
+/// `\u` and `\l` for the next character only, and `\n`, `\t`, `\r` and
+/// `\\`.
+pub struct Replacement {
+    parts: Vec<Part>,
+}
+
+enum Part {
+    Text(String),
+    Group(Group),
+    Case(Case),
+}
+
+enum Group {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Case {
+    Upper,
+    Lower,
+    End,
+    NextUpper,
+    NextLower,
+}
+
+impl Replacement {
+    /// `text` exactly as it is.
+    pub fn literal(text: &str) -> Replacement {
+        Replacement {
+            parts: vec![Part::Text(text.to_string())],
+        }
+    }
+
+    /// Reads `text`, checking that the groups it refers to exist in
+    /// `regex`. Fails with a message to show on the replace entry.
+    pub fn parse(text: &str, regex: &Regex) -> Result<Replacement, String> {
+        let replacement = Replacement::read(text)?;
+        for part in &replacement.parts {
+            if let Part::Group(group) = part {
+                group.check(regex)?;
+            }
+        }
+        Ok(replacement)
+    }
+
+    /// Reads `text` without looking at which groups the search has, for
+    /// searches that are not run by the `regex` crate.
+    pub fn read(text: &str) -> Result<Replacement, String> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = text;
+
+        while let Some(c) = rest.chars().next() {
+            rest = &rest[c.len_utf8()..];
+            let part = match c {
+                '\\' => {
+                    let escaped = rest
+                        .chars()
+                        .next()
+                        .ok_or_else(|| "The replacement ends with a lone “\\”".to_string())?;
+                    rest = &rest[escaped.len_utf8()..];
+                    match escaped {
+                        '0'..='9' => {
+                            // Like GRegex, up to two digits.
+                            let mut digits = escaped.to_string();
+                            if let Some(next) = rest.chars().next().filter(char::is_ascii_digit) {
+                                digits.push(next);
+                                rest = &rest[1..];
+                            }
+                            Part::Group(group(&digits))
+                        }
+                        'g' => {
+                            let close = match rest.strip_prefix('<').and_then(|inner| inner.find('>')) {
+                                Some(close) => close,
+                                None => return Err("“\\g” needs a group in angle brackets, as in \\g<1>".to_string()),
+                            };
+                            let name = &rest[1..close + 1];
+                            rest = &rest[close + 2..];
+                            Part::Group(group(name))
+                        }
+                        'U' => Part::Case(Case::Upper),
+                        'L' => Part::Case(Case::Lower),
+                        'E' => Part::Case(Case::End),
+                        'u' => Part::Case(Case::NextUpper),
+                        'l' => Part::Case(Case::NextLower),
+                        'n' => Part::Text("\n".to_string()),
+                        't' => Part::Text("\t".to_string()),
+                        'r' => Part::Text("\r".to_string()),
+                        '\\' => Part::Text("\\".to_string()),
+                        other => return Err(format!("Unknown escape “\\{}” in the replacement", other)),
+                    }
+                }
+                '$' if rest.starts_with('$') => {
+                    rest = &rest[1..];
+                    Part::Text("$".to_string())
+                }
+                '$' if rest.starts_with(|c: char| c.is_ascii_digit()) => {
+                    let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+                    let part = Part::Group(group(&rest[..digits]));
+                    rest = &rest[digits..];
+                    part
+                }
+                '$' if rest.starts_with('{') && rest.contains('}') => {
+                    let close = rest.find('}').unwrap();
+                    let part = Part::Group(group(&rest[1..close]));
+                    rest = &rest[close + 1..];
+                    part
+                }
+                c => {
+                    literal.push(c);
+                    continue;
+                }
+            };
+
+            if !literal.is_empty() {
+                parts.push(Part::Text(std::mem::take(&mut literal)));
+            }
+            parts.push(part);
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+
+        Ok(Replacement { parts })
+    }
+
+    /// Appends the replacement for the match `captures` to `out`.
+    pub fn expand(&self, captures: &Captures, out: &mut String) {
+        let mut case = Case::End;
+        let mut next = None;
+        for part in &self.parts {
+            let text = match part {
+                Part::Text(text) => text.as_str(),
+                Part::Group(Group::Index(index)) => captures.get(*index).map_or("", |found| found.as_str()),
+                Part::Group(Group::Name(name)) => captures.name(name).map_or("", |found| found.as_str()),
+                Part::Case(one @ Case::NextUpper) | Part::Case(one @ Case::NextLower) => {
+                    next = Some(*one);
+                    continue;
+                }
+                Part::Case(all) => {
+                    case = *all;
+                    continue;
+                }
+            };
+
+            for c in text.chars() {
+                match next.take().unwrap_or(case) {
+                    Case::Upper | Case::NextUpper => out.extend(c.to_uppercase()),
+                    Case::Lower | Case::NextLower => out.extend(c.to_lowercase()),
+                    Case::End => out.push(c),
+                }
+            }
+        }
+    }
+
+    /// The same replacement in the syntax GRegex expects, for the search
+    /// bar, where GtkSourceView does the replacing.
+    pub fn to_gregex(&self) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(&text.replace('\\', r"\\")),
+                Part::Group(Group::Index(index)) => out.push_str(&format!(r"\g<{}>", index)),
+                Part::Group(Group::Name(name)) => out.push_str(&format!(r"\g<{}>", name)),
+                Part::Case(Case::Upper) => out.push_str(r"\U"),
+                Part::Case(Case::Lower) => out.push_str(r"\L"),
+                Part::Case(Case::End) => out.push_str(r"\E"),
+                Part::Case(Case::NextUpper) => out.push_str(r"\u"),
+                Part::Case(Case::NextLower) => out.push_str(r"\l"),
+            }
+        }
+        out
+    }
+}
+
+fn group(reference: &str) -> Group {
+    match reference.parse::<usize>() {
+        Ok(index) => Group::Index(index),
+        Err(_) => Group::Name(reference.to_string()),
+    }
+}
+
+impl Group {
+    fn check(&self, regex: &Regex) -> Result<(), String> {
+        match self {
+            Group::Index(index) if *index >= regex.captures_len() => Err(format!("The search has no group {}", index)),
+            Group::Name(name) if !regex.capture_names().any(|found| found == Some(name.as_str())) => {
+                Err(format!("The search has no group named “{}”", name))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Replaces every match of `regex` in `text` with `replacement`, and also
+/// returns how many matches were replaced.
+pub fn replace_text(regex: &Regex, text: &str, replacement: &Replacement) -> (String, usize) {
+    let count = regex.find_iter(text).count();
+    let replaced = regex.replace_all(text, |captures: &Captures| {
+        let mut new = String::new();
+        replacement.expand(captures, &mut new);
+        new
+    });
+    (replaced.into_owned(), count)
+}
+
+/// Every match of `regex` in `text`, as the character offsets it starts and
+/// ends at, with what `replacement` turns it into.
+pub fn replacements(regex: &Regex, text: &str, replacement: &Replacement) -> Vec<(i32, i32, String)> {
+    let (mut chars, mut last) = (0, 0);
+    regex
+        .captures_iter(text)
+        .map(|captures| {
+            let found = captures.get(0).unwrap();
+            chars += text[last..found.start()].chars().count();
+            let start = chars;
+            chars += found.as_str().chars().count();
+            last = found.end();
+
+            let mut new = String::new();
+            replacement.expand(&captures, &mut new);
+            (start as i32, chars as i32, new)
+        })
+        .collect()
+}
+
+/// Replaces every match in the file at `path`, keeping its encoding, and
+/// returns how many were replaced. The file is left alone when nothing
+/// matches, and otherwise saved as safely as the editor saves; the fallback
+/// is returned when it had to be overwritten in place.
+pub fn replace_in_file(path: &Path, regex: &Regex, replacement: &Replacement) -> io::Result<(usize, Option<Fallback>)> {
+    let data = fs::read(path)?;
+    let encoding = encoding::detect(&data);
+    let text = encoding
+        .decode(&data)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "the file is not valid text"))?;
+
+    let (replaced, count) = replace_text(regex, &text, replacement);
+    if count == 0 {
+        return Ok((0, None));
+    }
+    match atomic::write(path, &encoding.encode(&replaced)?)? {
+        Written::Replaced => Ok((count, None)),
+        Written::InPlace(fallback) => Ok((count, Some(fallback))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace(pattern: &str, text: &str, replacement: &str) -> Result<String, String> {
+        let regex = Regex::new(pattern).unwrap();
+        let replacement = Replacement::parse(replacement, &regex)?;
+        Ok(replace_text(&regex, text, &replacement).0)
+    }
+
+    #[test]
+    fn backslash_groups() {
+        assert_eq!(replace(r"(\w+)=(\w+)", "a=b c=d", r"\2=\1").unwrap(), "b=a d=c");
+        assert_eq!(replace(r"(\w+)", "ab", r"\g<1>x").unwrap(), "abx");
+    }
+
+    #[test]
+    fn dollar_group_followed_by_text() {
+        assert_eq!(replace(r"(\d+)", "1 22", "$1a").unwrap(), "1a 22a");
+        assert_eq!(replace(r"(\d+)", "5", "$$1").unwrap(), "$1");
+    }
+
+    #[test]
+    fn dollar_signs() {
+        let ten = r"(a)(b)(c)(d)(e)(f)(g)(h)(i)(j)";
+        assert_eq!(replace(ten, "abcdefghij", "${10}$1").unwrap(), "ja");
+        assert_eq!(replace(r"(\d+)", "5", "$$").unwrap(), "$");
+        assert_eq!(replace(r"(\d+)", "5", "$1$").unwrap(), "5$");
+        assert_eq!(replace(r"(\d+)", "5", "${1").unwrap(), "${1");
+    }
+
+    #[test]
+    fn literal_backslashes() {
+        assert_eq!(replace(r"(\d+)", "5", r"\\1").unwrap(), r"\1");
+        assert_eq!(replace(r"(\d+)", "5", r"a\\").unwrap(), r"a\");
+    }
+
+    #[test]
+    fn gregex_syntax() {
+        let gregex = |text| Replacement::read(text).unwrap().to_gregex();
+        assert_eq!(gregex("$1-${10}-${word}"), r"\g<1>-\g<10>-\g<word>");
+        assert_eq!(gregex(r"\2\g<3>"), r"\g<2>\g<3>");
+        assert_eq!(gregex("$$1 costs 5$"), "$1 costs 5$");
+        assert_eq!(gregex(r"a\\b\n"), "a\\\\b\n");
+        assert_eq!(gregex(r"\U$1\E\u\l\L"), r"\U\g<1>\E\u\l\L");
+        assert!(Replacement::read(r"\q").is_err());
+    }
+
+    #[test]
+    fn named_groups() {
+        assert_eq!(replace(r"(?P<word>\w+)", "hi", "<${word}>").unwrap(), "<hi>");
+        assert_eq!(replace(r"(?P<word>\w+)", "hi", r"\g<word>!").unwrap(), "hi!");
+    }
+
+    #[test]
+    fn case_escapes() {
+        assert_eq!(replace(r"(\w+) (\w+)", "ab cd", r"\U\1\E \u\2").unwrap(), "AB Cd");
+        assert_eq!(replace(r"(\w+)", "ABC", r"\l\1 \L\1").unwrap(), "aBC abc");
+    }
+
+    #[test]
+    fn literal_replacement() {
+        let regex = Regex::new("a").unwrap();
+        assert_eq!(replace_text(&regex, "aba", &Replacement::literal(r"$1\1")).0, r"$1\1b$1\1");
+    }
+
+    #[test]
+    fn replacements_count_characters() {
+        let regex = Regex::new(r"(\w+)@").unwrap();
+        let replacement = Replacement::parse(r"<\1>", &regex).unwrap();
+        assert_eq!(
+            replacements(&regex, "é ab@ ü cd@", &replacement),
+            vec![(2, 5, "<ab>".to_string()), (8, 11, "<cd>".to_string())]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_groups_and_escapes() {
+        assert!(replace(r"(\w)", "a", r"\2").is_err());
+        assert!(replace(r"(\w)", "a", "${name}").is_err());
+        assert!(replace(r"(\w)", "a", r"\q").is_err());
+        assert!(replace(r"(\w)", "a", "\\").is_err());
+    }
+}
@@ -0,0 +1,279 @@
+/*
+ * Copyright (c) 2020 mazen (mmaz999@outlook.com)
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Library General Public License as published by
+ * the Free Software Foundation, either version 2.1 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * Authored by: mazen <https://github.com/maze-n>
+ */
+
+use crate::atomic;
+use crate::recovery::{create_journal_dir, journal_dir};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+const HEADER: &str = "eddit-session 2\n";
+
+/// What was open when the editor was last quit.
+#[derive(Default)]
+pub struct Session {
+    pub documents: Vec<DocumentState>,
+    /// The index of the active document.
+    pub active: usize,
+}
+
+/// One tab of a session. Offsets count characters from the start of the
+/// text.
+#[derive(Default)]
+pub struct DocumentState {
+    pub path: Option<PathBuf>,
+    /// The text, when it had changes that were not saved.
+    pub unsaved: Option<String>,
+    pub cursor: i32,
+    pub selection_bound: i32,
+    /// Where the first line shown in the view starts.
+    pub scroll: i32,
+    pub language: Option<String>,
+    pub encoding: Option<String>,
+}
+
+fn session_file() -> PathBuf {
+    journal_dir().join("session")
+}
+
+/// Writes `session` over the saved one.
+pub fn save(session: &Session) -> io::Result<()> {
+    create_journal_dir()?;
+    atomic::write_private(&session_file(), &encode(session)).map(|_| ())
+}
+
+/// The saved form of `session`. Each document is a block prefixed with its
+/// length, and paths and text are prefixed with theirs, as they may hold
+/// anything, line breaks included. A damaged document then costs only
+/// itself.
+fn encode(session: &Session) -> Vec<u8> {
+    let mut data = HEADER.as_bytes().to_vec();
+    for (index, document) in session.documents.iter().enumerate() {
+        let mut block = Vec::new();
+        if index == session.active {
+            block.extend_from_slice(b"active\n");
+        }
+        if let Some(ref path) = document.path {
+            push_bytes(&mut block, "path", path.as_os_str().as_bytes());
+        }
+        block.extend_from_slice(format!("cursor {} {}\n", document.cursor, document.selection_bound).as_bytes());
+        block.extend_from_slice(format!("scroll {}\n", document.scroll).as_bytes());
+        if let Some(ref language) = document.language {
+            block.extend_from_slice(format!("language {}\n", language).as_bytes());
+        }
+        if let Some(ref encoding) = document.encoding {
+            block.extend_from_slice(format!("encoding {}\n", encoding).as_bytes());
+        }
+        if let Some(ref text) = document.unsaved {
+            push_bytes(&mut block, "unsaved", text.as_bytes());
+        }
+        push_bytes(&mut data, "document", &block);
+    }
+    data
+}
+
+/// Appends `key`, the length of `value`, and `value` on the lines after.
+fn push_bytes(data: &mut Vec<u8>, key: &str, value: &[u8]) {
+    data.extend_from_slice(format!("{} {}\n", key, value.len()).as_bytes());
+    data.extend_from_slice(value);
+    data.push(b'\n');
+}
+
+/// The saved session, if there is one and it can be read.
+pub fn load() -> Option<Session> {
+    parse(&fs::read(session_file()).ok()?)
+}
+
+/// Forgets the saved session, once it has been restored.
+pub fn remove() {
+    let _ = fs::remove_file(session_file());
+}
+
+/// Reads a saved session. Documents that do not parse are left out, and a
+/// file cut short keeps the documents before the cut.
+fn parse(data: &[u8]) -> Option<Session> {
+    let mut rest = data.strip_prefix(HEADER.as_bytes())?;
+    let mut session = Session::default();
+
+    while let Some((key, value)) = next_line(&mut rest) {
+        // Lines from a newer version are skipped.
+        if key != b"document" {
+            continue;
+        }
+        let block = match take_bytes(&mut rest, value) {
+            Some(block) => block,
+            None => break,
+        };
+        if let Some((document, active)) = parse_document(block) {
+            if active {
+                session.active = session.documents.len();
+            }
+            session.documents.push(document);
+        }
+    }
+
+    Some(session)
+}
+
+/// Reads one document block, and whether it was the active one.
+fn parse_document(mut rest: &[u8]) -> Option<(DocumentState, bool)> {
+    let mut document = DocumentState::default();
+    let mut active = false;
+
+    while let Some((key, value)) = next_line(&mut rest) {
+        match key {
+            b"active" => active = true,
+            b"path" => {
+                let path = take_bytes(&mut rest, value)?;
+                document.path = Some(PathBuf::from(OsStr::from_bytes(path)));
+            }
+            b"unsaved" => {
+                let text = take_bytes(&mut rest, value)?;
+                document.unsaved = Some(String::from_utf8(text.to_vec()).ok()?);
+            }
+            _ => {
+                let value = std::str::from_utf8(value).ok()?;
+                match key {
+                    b"cursor" => {
+                        let mut offsets = value.split(' ').map(str::parse);
+                        document.cursor = offsets.next()?.ok()?;
+                        document.selection_bound = offsets.next()?.ok()?;
+                    }
+                    b"scroll" => document.scroll = value.parse().ok()?,
+                    b"language" => document.language = Some(value.to_string()),
+                    b"encoding" => document.encoding = Some(value.to_string()),
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    Some((document, active))
+}
+
+/// Splits the next line off `rest` into its key and the value after the
+/// first space.
+fn next_line<'a>(rest: &mut &'a [u8]) -> Option<(&'a [u8], &'a [u8])> {
+    let end = rest.iter().position(|&b| b == b'\n')?;
+    let line = &rest[..end];
+    *rest = &rest[end + 1..];
+
+    let space = line.iter().position(|&b| b == b' ').unwrap_or(line.len());
+    Some((&line[..space], line.get(space + 1..).unwrap_or_default()))
+}
+
+/// Splits off the bytes whose length `length` gives, and the line break
+/// after them.
+fn take_bytes<'a>(rest: &mut &'a [u8], length: &[u8]) -> Option<&'a [u8]> {
+    let length: usize = std::str::from_utf8(length).ok()?.parse().ok()?;
+    let bytes = rest.get(..length)?;
+    if rest.get(length) != Some(&b'\n') {
+        return None;
+    }
+    *rest = &rest[length + 1..];
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Session {
+        Session {
+            documents: vec![
+                DocumentState {
+                    path: Some(PathBuf::from("/tmp/odd\nname.txt")),
+                    unsaved: Some("document 3\nline\n\n".to_string()),
+                    cursor: 4,
+                    selection_bound: 2,
+                    scroll: 1,
+                    language: Some("rust".to_string()),
+                    encoding: Some("windows-1252".to_string()),
+                },
+                DocumentState {
+                    path: Some(PathBuf::from("/tmp/plain.txt")),
+                    cursor: 7,
+                    selection_bound: 7,
+                    ..DocumentState::default()
+                },
+                DocumentState {
+                    unsaved: Some("untitled".to_string()),
+                    ..DocumentState::default()
+                },
+            ],
+            active: 1,
+        }
+    }
+
+    fn paths(session: &Session) -> Vec<Option<PathBuf>> {
+        session.documents.iter().map(|document| document.path.clone()).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let saved = sample();
+        let loaded = parse(&encode(&saved)).unwrap();
+
+        assert_eq!(loaded.active, 1);
+        assert_eq!(paths(&loaded), paths(&saved));
+        let first = &loaded.documents[0];
+        assert_eq!(first.unsaved.as_deref(), Some("document 3\nline\n\n"));
+        assert_eq!((first.cursor, first.selection_bound, first.scroll), (4, 2, 1));
+        assert_eq!(first.language.as_deref(), Some("rust"));
+        assert_eq!(first.encoding.as_deref(), Some("windows-1252"));
+        assert_eq!(loaded.documents[1].unsaved, None);
+        assert_eq!(loaded.documents[2].unsaved.as_deref(), Some("untitled"));
+    }
+
+    #[test]
+    fn truncated_file_keeps_earlier_documents() {
+        let data = encode(&sample());
+        let cut = data.len() - 5;
+        let loaded = parse(&data[..cut]).unwrap();
+        assert_eq!(paths(&loaded), paths(&sample())[..2].to_vec());
+    }
+
+    #[test]
+    fn damaged_document_is_left_out() {
+        // The unsaved text is shorter than its length says.
+        let mut data = HEADER.as_bytes().to_vec();
+        push_bytes(&mut data, "document", b"path 6\n/a.txt\nunsaved 9\nabc\n");
+        push_bytes(&mut data, "document", b"active\npath 6\n/b.txt\n");
+        let loaded = parse(&data).unwrap();
+        assert_eq!(paths(&loaded), vec![Some(PathBuf::from("/b.txt"))]);
+        assert_eq!(loaded.active, 0);
+    }
+
+    #[test]
+    fn unknown_lines_are_skipped() {
+        let mut data = HEADER.as_bytes().to_vec();
+        data.extend_from_slice(b"something new\n");
+        push_bytes(&mut data, "document", b"path 6\n/c.txt\ncolor red\n");
+        let loaded = parse(&data).unwrap();
+        assert_eq!(paths(&loaded), vec![Some(PathBuf::from("/c.txt"))]);
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        assert!(parse(b"").is_none());
+        assert!(parse(b"eddit-session 1\nactive 0\n").is_none());
+    }
+}
@@ -17,19 +17,40 @@
  * Authored by: mazen <https://github.com/maze-n>
  */
 
+use crate::encoding::TextEncoding;
+use crate::line_ending::LineEnding;
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use tiny_keccak::keccak512;
 
+/// What the file looked like on disk when it was last loaded or saved.
+struct DiskState {
+    modified: Option<SystemTime>,
+    len: u64,
+    sum: [u8; 64],
+}
+
 pub struct ActiveMetadata {
     path: PathBuf,
     sum: [u8; 64],
+    encoding: TextEncoding,
+    saved_encoding: TextEncoding,
+    line_ending: LineEnding,
+    saved_line_ending: Option<LineEnding>,
+    disk: Option<DiskState>,
 }
 
 impl ActiveMetadata {
-    pub fn new(path: PathBuf, data: &[u8]) -> ActiveMetadata {
+    pub fn new(path: PathBuf, data: &[u8], encoding: TextEncoding, line_ending: LineEnding) -> ActiveMetadata {
         ActiveMetadata {
             path,
             sum: keccak512(data),
+            encoding,
+            saved_encoding: encoding,
+            line_ending,
+            saved_line_ending: Some(line_ending),
+            disk: None,
         }
     }
 
@@ -41,11 +62,82 @@ impl ActiveMetadata {
         self.path.parent().map(|p| p.to_path_buf())
     }
 
-    pub fn is_same_as(&self, data: &[u8]) -> bool {
+    pub fn get_encoding(&self) -> TextEncoding {
+        self.encoding
+    }
+
+    /// Changes the encoding the next save writes with. Until then the
+    /// document counts as modified, even if the text is unchanged.
+    pub fn set_encoding(&mut self, encoding: TextEncoding) {
+        self.encoding = encoding;
+    }
+
+    pub fn get_line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// Changes the line ending the next save converts the document to.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.line_ending = line_ending;
+    }
+
+    /// Records that the file on disk mixes line endings, so it differs from
+    /// the normalized buffer until it is saved.
+    pub fn set_mixed_line_endings(&mut self) {
+        self.saved_line_ending = None;
+    }
+
+    /// Whether `data` is the text that was last loaded or saved.
+    pub fn has_same_text(&self, data: &[u8]) -> bool {
         &keccak512(data)[..] == &self.sum[..]
     }
 
+    /// Whether the encoding and line ending are still the ones on disk.
+    pub fn is_format_saved(&self) -> bool {
+        self.encoding == self.saved_encoding && Some(self.line_ending) == self.saved_line_ending
+    }
+
     pub fn set_sum(&mut self, data: &[u8]) {
         self.sum = keccak512(data);
+        self.saved_encoding = self.encoding;
+        self.saved_line_ending = Some(self.line_ending);
+    }
+
+    /// Replaces the sum the buffer is compared against, keeping the encoding
+    /// and line ending last saved.
+    pub fn set_text_sum(&mut self, data: &[u8]) {
+        self.sum = keccak512(data);
+    }
+
+    /// Remembers `data` as the raw bytes now on disk, along with the file's
+    /// current size and modification time.
+    pub fn set_disk_state(&mut self, data: &[u8]) {
+        let metadata = fs::metadata(&self.path).ok();
+        self.disk = Some(DiskState {
+            modified: metadata.as_ref().and_then(|metadata| metadata.modified().ok()),
+            len: metadata.map_or(data.len() as u64, |metadata| metadata.len()),
+            sum: keccak512(data),
+        });
+    }
+
+    /// Whether the file still holds what was last loaded or saved. The file
+    /// is only read again when its size or modification time moved, and a
+    /// file that disappeared counts as changed.
+    pub fn is_unchanged_on_disk(&self) -> bool {
+        let disk = match self.disk {
+            Some(ref disk) => disk,
+            None => return true,
+        };
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(_) => return false,
+        };
+        if metadata.len() != disk.len {
+            return false;
+        }
+        if disk.modified.is_some() && metadata.modified().ok() == disk.modified {
+            return true;
+        }
+        fs::read(&self.path).map_or(false, |data| keccak512(&data)[..] == disk.sum[..])
     }
 }